@@ -1,7 +1,7 @@
 use rollup_core::{
     db::Storage,
-    sequencer::{commit_batch, compute_state_commitment},
-    types::{Account, BlockHeader, Pubkey, Signature, Transaction, TransactionType},
+    sequencer::{commit_batch, merkle_root, SparseMerkle, StateCommitment},
+    types::{Account, AccountKey, BlockHeader, ChainId, Pubkey, Signature, Transaction, TransactionType, NATIVE_MINT},
 };
 use std::{collections::{BTreeMap, HashMap}, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
 use rand::{rngs::StdRng, Rng, SeedableRng};
@@ -43,18 +43,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     for _ in 0..NUM_ACCOUNTS {
         let pubkey = Pubkey::new(rng.random());
         let account = Account { balance: 1_000_000, nonce: 0 };
-        initial_batch.put_cf(cf, &pubkey.0, &bincode::serialize(&account)?);
-        all_accounts.insert(pubkey, account);
+        let key = AccountKey::native(pubkey);
+        initial_batch.put_cf(cf, key.to_bytes(), bincode::serialize(&account)?);
+        all_accounts.insert(key, account);
         pb.inc(1);
     }
-    storage.rocksdb.write(initial_batch)?;
     pb.finish_with_message("done");
 
+    storage.rocksdb.write(initial_batch)?;
+
+    println!("Building the state tree...");
+    let mut commitment = SparseMerkle::default();
+    let mut root = commitment.root(&storage, &tip)?;
+
     println!("\nSimulating and committing {} blocks...", NUM_BLOCKS);
     let pb_blocks = ProgressBar::new(NUM_BLOCKS);
     pb_blocks.set_style(ProgressStyle::default_bar().template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")?);
 
-    let account_pks: Vec<Pubkey> = all_accounts.keys().cloned().collect();
+    let account_pks: Vec<Pubkey> = all_accounts.keys().map(|key| key.owner).collect();
 
     for _ in 0..NUM_BLOCKS {
         let mut write_set = HashMap::new();
@@ -63,35 +69,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         for _ in 0..TX_PER_BLOCK {
             let sender_pk = account_pks[rng.random_range(0..account_pks.len())];
             let recipient_pk = account_pks[rng.random_range(0..account_pks.len())];
-            let mut sender = all_accounts.get(&sender_pk).unwrap().clone();
+            let mut sender = all_accounts.get(&AccountKey::native(sender_pk)).unwrap().clone();
             
             if sender.balance > 1 {
+                let nonce = sender.nonce;
                 sender.balance -= 1;
                 sender.nonce += 1;
-                write_set.insert(sender_pk, sender);
+                write_set.insert(AccountKey::native(sender_pk), sender);
 
                 transactions.push(Transaction {
+                    chain_id: ChainId::DEVNET,
                     sender: sender_pk,
                     recipient: recipient_pk,
-                    tx_type: TransactionType::Transfer { amount: 1 },
+                    tx_type: TransactionType::Transfer { amount: 1, mint: NATIVE_MINT },
+                    nonce,
+                    fee: 0,
+                    valid_until_batch: None,
                     signature: Signature(rng.random()),
                 });
             }
         }
         
         all_accounts.extend(write_set.clone());
-        let new_root = compute_state_commitment(&all_accounts, tip.batch_id + 1);
+        let update = commitment.update(&storage, &tip, &write_set)?;
         
         let header = BlockHeader {
             batch_id: tip.batch_id + 1,
-            prev_root: tip.new_root,
-            new_root,
+            prev_root: root,
+            new_root: update.root,
             tx_count: transactions.len() as u32,
             open_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            tx_root: merkle_root(&transactions.iter().map(|tx| tx.hash().0).collect::<Vec<_>>()),
             ..BlockHeader::genesis()
         };
 
-        commit_batch(&storage, &header, &write_set, &transactions).await?;
+        commit_batch(&storage, &header, &write_set, &transactions, &[], &[], &update.nodes).await?;
+        root = update.root;
         tip = header;
         pb_blocks.inc(1);
     }
@@ -3,11 +3,11 @@ use std::{env, path::PathBuf, sync::Arc};
 use rocksdb::IteratorMode;
 use rollup_core::{
     db::{Storage, CF_NAMES},
-    types::{Account, Pubkey, Transaction},
+    sequencer::StateNode,
+    types::{Account, AccountKey, BlockHeader, Pubkey, Receipt, Transaction, Withdrawal},
 };
 
 use chrono::{DateTime, TimeZone, Utc};
-use hex;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -39,11 +39,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                 for entry in storage.rocksdb.iterator_cf(&cf, IteratorMode::Start) {
                     let (key_bytes, value_bytes) = entry?;
-                    let pubkey: Pubkey = bincode::deserialize(&key_bytes)?;
+                    let key = AccountKey::from_bytes(&key_bytes).ok_or("Invalid account key")?;
                     let account: Account = bincode::deserialize(&value_bytes)?;
 
                     rows.push(vec![
-                        hex::encode(pubkey.0),
+                        hex::encode(key.owner.0),
+                        hex::encode(key.mint.0),
                         account.balance.to_string(),
                         account.nonce.to_string(),
                     ]);
@@ -54,9 +55,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     print_empty_table();
                 } else {
                     print_wrapped_table(
-                        &["Pubkey", "Balance", "Nonce"],
-                        &[44, 11, 5],
-                        &["<", ">", ">"],
+                        &["Owner", "Mint", "Balance", "Nonce"],
+                        &[44, 44, 11, 5],
+                        &["<", "<", ">", ">"],
                         &rows,
                     );
                 }
@@ -99,22 +100,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let mut rows: Vec<Vec<String>> = Vec::new();
 
                 for entry in storage.rocksdb.iterator_cf(&cf, IteratorMode::Start) {
-                    let (key_bytes, value_bytes) = entry?;
-
-                    if key_bytes.len() >= 8 && value_bytes.len() >= 4 + 2 + 8 + 32 + 32 {
-                        let batch_id = u64::from_be_bytes(key_bytes[..8].try_into().unwrap());
-                        let magic = &value_bytes[0..4];
-                        let hdr_version =
-                            u16::from_be_bytes(value_bytes[4..6].try_into().unwrap());
-                        let new_root = &value_bytes[46..78];
+                    let (_, value_bytes) = entry?;
+                    let header = BlockHeader::from_bytes(&value_bytes)?;
 
-                        rows.push(vec![
-                            batch_id.to_string(),
-                            hex::encode(magic),
-                            hdr_version.to_string(),
-                            hex::encode(new_root),
-                        ]);
-                    }
+                    rows.push(vec![
+                        header.batch_id.to_string(),
+                        hex::encode(header.magic),
+                        header.hdr_version.to_string(),
+                        hex::encode(header.new_root),
+                        hex::encode(header.tx_root),
+                        hex::encode(header.receipts_root),
+                    ]);
                 }
 
                 print_table_header("BATCHES", rows.len());
@@ -122,9 +118,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     print_empty_table();
                 } else {
                     print_wrapped_table(
-                        &["Batch", "Magic", "Version", "New Root"],
-                        &[6, 8, 7, 44],
-                        &[">", "<", ">", "<"],
+                        &["Batch", "Magic", "Version", "New Root", "Tx Root", "Receipts Root"],
+                        &[6, 8, 7, 44, 44, 44],
+                        &[">", "<", ">", "<", "<", "<"],
                         &rows,
                     );
                 }
@@ -210,6 +206,138 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
+            // ========== WITHDRAWALS ==========
+            "withdrawals" => {
+                let cf = storage.cf_withdrawals();
+                let mut rows: Vec<Vec<String>> = Vec::new();
+
+                for entry in storage.rocksdb.iterator_cf(&cf, IteratorMode::Start) {
+                    let (key_bytes, value_bytes) = entry?;
+
+                    if key_bytes.len() >= 8 + 4 {
+                        let batch_id = u64::from_be_bytes(key_bytes[..8].try_into().unwrap());
+                        let index = u32::from_be_bytes(key_bytes[8..12].try_into().unwrap());
+                        let withdrawal: Withdrawal = bincode::deserialize(&value_bytes)?;
+
+                        rows.push(vec![
+                            format!("{}/{}", batch_id, index),
+                            hex::encode(withdrawal.sender.0),
+                            hex::encode(withdrawal.l1_recipient.0),
+                            hex::encode(withdrawal.mint.0),
+                            withdrawal.amount.to_string(),
+                        ]);
+                    }
+                }
+
+                print_table_header("WITHDRAWALS", rows.len());
+                if rows.is_empty() {
+                    print_empty_table();
+                } else {
+                    print_wrapped_table(
+                        &["Batch/Idx", "Sender", "L1 Recipient", "Mint", "Amount"],
+                        &[10, 44, 44, 44, 11],
+                        &["<", "<", "<", "<", ">"],
+                        &rows,
+                    );
+                }
+            }
+
+            // ========== RECEIPTS ==========
+            "receipts" => {
+                let cf = storage.cf_receipts();
+                let mut rows: Vec<Vec<String>> = Vec::new();
+
+                for entry in storage.rocksdb.iterator_cf(&cf, IteratorMode::Start) {
+                    let (key_bytes, value_bytes) = entry?;
+                    let receipt: Receipt = bincode::deserialize(&value_bytes)?;
+
+                    rows.push(vec![
+                        hex::encode(&key_bytes),
+                        format!("{}/{}", receipt.batch_id, receipt.index),
+                        format!("{:?}", receipt.status),
+                        receipt.error_code.unwrap_or_default(),
+                        receipt.balance_changes.len().to_string(),
+                    ]);
+                }
+
+                print_table_header("RECEIPTS", rows.len());
+                if rows.is_empty() {
+                    print_empty_table();
+                } else {
+                    print_wrapped_table(
+                        &["Transaction ID", "Batch/Idx", "Status", "Error", "Changes"],
+                        &[44, 10, 8, 18, 7],
+                        &["<", "<", "<", "<", ">"],
+                        &rows,
+                    );
+                }
+            }
+
+            // ========== STATE NODES ==========
+            "state_nodes" => {
+                let cf = storage.cf_state_nodes();
+                let mut rows: Vec<Vec<String>> = Vec::new();
+
+                for entry in storage.rocksdb.iterator_cf(&cf, IteratorMode::Start) {
+                    let (key_bytes, value_bytes) = entry?;
+                    let node: StateNode = bincode::deserialize(&value_bytes)?;
+
+                    let (kind, detail) = match node {
+                        StateNode::Internal { left, right } => {
+                            ("Internal", format!("{} | {}", hex::encode(left), hex::encode(right)))
+                        }
+                        StateNode::Leaf { key, account } => (
+                            "Leaf",
+                            format!("{}/{} balance {} nonce {}", hex::encode(key.owner.0), hex::encode(key.mint.0), account.balance, account.nonce),
+                        ),
+                    };
+                    rows.push(vec![hex::encode(&key_bytes), kind.to_string(), detail]);
+                }
+
+                print_table_header("STATE NODES", rows.len());
+                if rows.is_empty() {
+                    print_empty_table();
+                } else {
+                    print_wrapped_table(
+                        &["Hash", "Kind", "Children / Account"],
+                        &[44, 8, 60],
+                        &["<", "<", "<"],
+                        &rows,
+                    );
+                }
+            }
+
+            // ========== PENDING_TXS ==========
+            "pending_txs" => {
+                let cf = storage.cf_pending_txs();
+                let mut rows: Vec<Vec<String>> = Vec::new();
+
+                for entry in storage.rocksdb.iterator_cf(&cf, IteratorMode::Start) {
+                    let (key_bytes, value_bytes) = entry?;
+                    let (received_at, tx): (u64, Transaction) = bincode::deserialize(&value_bytes)?;
+
+                    rows.push(vec![
+                        hex::encode(&key_bytes),
+                        decode_timestamp_nanos(received_at).to_rfc3339(),
+                        hex::encode(tx.sender.0),
+                        tx.nonce.to_string(),
+                        tx.fee.to_string(),
+                    ]);
+                }
+
+                print_table_header("PENDING TXS", rows.len());
+                if rows.is_empty() {
+                    print_empty_table();
+                } else {
+                    print_wrapped_table(
+                        &["Transaction ID", "Received", "Sender", "Nonce", "Fee"],
+                        &[44, 25, 44, 6, 10],
+                        &["<", "<", "<", ">", ">"],
+                        &rows,
+                    );
+                }
+            }
+
             _ => {
                 print_table_header(&cf_name.to_uppercase(), 0);
                 println!("╔════════════════════════════════════════════════════════════════════╗");
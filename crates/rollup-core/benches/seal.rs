@@ -0,0 +1,138 @@
+//! Time to seal one batch of transfers on top of states of different sizes.
+//!
+//! Each iteration signs and executes `TX_PER_BATCH` transfers, updates the
+//! state tree from the write set and commits, as `RollupCore` does for a
+//! batch. Only the accounts a batch writes are touched, so the time per
+//! batch should stay flat as the number of stored accounts grows.
+//!
+//!     cargo bench -p rollup-core --bench seal
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ed25519_dalek::SigningKey;
+use rocksdb::WriteBatch;
+use rollup_core::db::Storage;
+use rollup_core::sequencer::{commit_batch, merkle_root, BatchContext, FeeConfig, SparseMerkle, StateCommitment};
+use rollup_core::types::{Account, AccountKey, BlockHeader, ChainId, Pubkey, Receipt, Signature, Transaction, TransactionType, NATIVE_MINT};
+use std::collections::BTreeMap;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+const TX_PER_BATCH: u64 = 10;
+const ACCOUNT_COUNTS: [u64; 3] = [1_000, 10_000, 100_000];
+
+struct Chain {
+    storage: Storage,
+    _dir: TempDir,
+    senders: Vec<(SigningKey, u64)>,
+    num_accounts: u64,
+    commitment: SparseMerkle,
+    tip: BlockHeader,
+}
+
+/// Owner of the `i`th filler account.
+fn filler_owner(i: u64) -> Pubkey {
+    let mut owner = [0xff; 32];
+    owner[..8].copy_from_slice(&i.to_be_bytes());
+    Pubkey::new(owner)
+}
+
+impl Chain {
+    /// A chain whose state holds `num_accounts` accounts, `TX_PER_BATCH` of
+    /// which are funded senders, with the state tree already built.
+    fn seeded(rt: &Runtime, num_accounts: u64) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = rt.block_on(Storage::new(
+            dir.path().join("rocksdb").to_str().unwrap(),
+            dir.path().join("checkpoints.db").to_str().unwrap(),
+        )).unwrap();
+
+        let senders: Vec<_> = (0..TX_PER_BATCH).map(|i| (SigningKey::from_bytes(&[i as u8 + 1; 32]), 0)).collect();
+        let mut accounts = BTreeMap::new();
+        for (key, _) in &senders {
+            accounts.insert(AccountKey::native(Pubkey::new(key.verifying_key().to_bytes())), Account { balance: u64::MAX / 2, nonce: 0 });
+        }
+        for i in 0..num_accounts - TX_PER_BATCH {
+            accounts.insert(AccountKey::native(filler_owner(i)), Account { balance: 1_000_000, nonce: 0 });
+        }
+
+        let mut batch = WriteBatch::default();
+        for (key, account) in &accounts {
+            batch.put_cf(storage.cf_accounts(), key.to_bytes(), bincode::serialize(account).unwrap());
+        }
+        storage.rocksdb.write(batch).unwrap();
+
+        // Builds the tree from the seeded accounts, as on a node's first batch.
+        let mut commitment = SparseMerkle::default();
+        let tip = BlockHeader::genesis();
+        commitment.root(&storage, &tip).unwrap();
+        Self { storage, _dir: dir, senders, num_accounts, commitment, tip }
+    }
+
+    /// One signed transfer from every sender, each to an existing account.
+    fn next_transactions(&mut self) -> Vec<Transaction> {
+        let batch_id = self.tip.batch_id;
+        let fillers = self.num_accounts - TX_PER_BATCH;
+        self.senders.iter_mut().enumerate()
+            .map(|(i, (key, nonce))| {
+                let mut tx = Transaction {
+                    chain_id: ChainId::DEVNET,
+                    sender: Pubkey::new(key.verifying_key().to_bytes()),
+                    recipient: filler_owner((batch_id * TX_PER_BATCH + i as u64) * 7919 % fillers),
+                    tx_type: TransactionType::Transfer { amount: 1, mint: NATIVE_MINT },
+                    nonce: *nonce,
+                    fee: 0,
+                    valid_until_batch: None,
+                    signature: Signature([0; 64]),
+                };
+                tx.sign(key);
+                *nonce += 1;
+                tx
+            })
+            .collect()
+    }
+
+    fn seal(&mut self, rt: &Runtime, transactions: &[Transaction]) {
+        let batch_id = self.tip.batch_id + 1;
+        let mut batch_context = BatchContext::new(&self.storage, FeeConfig { collector: Pubkey::new([0xfe; 32]), min_fee: 0 }, ChainId::DEVNET, batch_id);
+        batch_context.execute_batch(transactions).unwrap();
+        let update = self.commitment.update(&self.storage, &self.tip, &batch_context.write_set).unwrap();
+        let header = BlockHeader {
+            batch_id,
+            prev_root: self.tip.new_root,
+            new_root: update.root,
+            tx_count: batch_context.included_count() as u32,
+            tx_root: merkle_root(&transactions.iter().map(|tx| tx.hash().0).collect::<Vec<_>>()),
+            receipts_root: merkle_root(&batch_context.receipts.iter().map(Receipt::hash).collect::<Vec<_>>()),
+            ..BlockHeader::genesis()
+        };
+        rt.block_on(commit_batch(
+            &self.storage,
+            &header,
+            &batch_context.write_set,
+            transactions,
+            &batch_context.withdrawals,
+            &batch_context.receipts,
+            &update.nodes,
+        )).unwrap();
+        self.tip = header;
+    }
+}
+
+fn seal_batch(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("seal_batch");
+    group.sample_size(20);
+    for num_accounts in ACCOUNT_COUNTS {
+        let mut chain = Chain::seeded(&rt, num_accounts);
+        group.bench_with_input(BenchmarkId::from_parameter(num_accounts), &num_accounts, |b, _| {
+            b.iter(|| {
+                let transactions = chain.next_transactions();
+                chain.seal(&rt, &transactions);
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, seal_batch);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use std::fmt;
+
+/// Failure reading or writing RocksDB or the SQLite checkpoint database.
+#[derive(Debug)]
+pub enum DbError {
+    RocksDb(rocksdb::Error),
+    Sqlite(sqlx::Error),
+    /// A stored value could not be encoded or decoded with bincode.
+    Codec(bincode::Error),
+    Io(std::io::Error),
+    /// A stored key or value has the wrong shape.
+    Corrupt(String),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::RocksDb(e) => write!(f, "RocksDB error: {}", e),
+            DbError::Sqlite(e) => write!(f, "SQLite error: {}", e),
+            DbError::Codec(e) => write!(f, "Codec error: {}", e),
+            DbError::Io(e) => write!(f, "I/O error: {}", e),
+            DbError::Corrupt(msg) => write!(f, "Corrupt record: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::RocksDb(e) => Some(e),
+            DbError::Sqlite(e) => Some(e),
+            DbError::Codec(e) => Some(e),
+            DbError::Io(e) => Some(e),
+            DbError::Corrupt(_) => None,
+        }
+    }
+}
+
+impl From<rocksdb::Error> for DbError {
+    fn from(e: rocksdb::Error) -> Self { DbError::RocksDb(e) }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(e: sqlx::Error) -> Self { DbError::Sqlite(e) }
+}
+
+impl From<bincode::Error> for DbError {
+    fn from(e: bincode::Error) -> Self { DbError::Codec(e) }
+}
+
+impl From<std::io::Error> for DbError {
+    fn from(e: std::io::Error) -> Self { DbError::Io(e) }
+}
@@ -1,6 +1,10 @@
+mod error;
+mod pending;
 mod recovery;
 mod storage;
 
+pub use error::DbError;
+pub use pending::{forget_pending, journal_pending, load_pending};
 pub use recovery::reconcile_databases_on_startup;
 pub use storage::Storage;
 
@@ -0,0 +1,42 @@
+use super::error::DbError;
+use super::storage::Storage;
+use crate::types::{Transaction, TxHash};
+use chrono::Utc;
+use rocksdb::{IteratorMode, WriteBatch, WriteOptions};
+
+/// Records an accepted transaction in `cf_pending_txs`, keyed by its hash,
+/// so `RollupCore::new` can restore it after a restart. `commit_batch`
+/// removes it once a batch includes it.
+pub fn journal_pending(storage: &Storage, tx: &Transaction) -> Result<(), DbError> {
+    let received_at = Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64;
+    let mut batch = WriteBatch::default();
+    batch.put_cf(storage.cf_pending_txs(), tx.hash().0, bincode::serialize(&(received_at, tx))?);
+    let mut write_opts = WriteOptions::default();
+    write_opts.set_sync(true);
+    storage.rocksdb.write_opt(batch, &write_opts)?;
+    Ok(())
+}
+
+/// Removes transactions that will never be included from the journal.
+pub fn forget_pending(storage: &Storage, hashes: impl IntoIterator<Item = TxHash>) -> Result<(), DbError> {
+    let mut batch = WriteBatch::default();
+    for hash in hashes {
+        batch.delete_cf(storage.cf_pending_txs(), hash.0);
+    }
+    if !batch.is_empty() {
+        storage.rocksdb.write(batch)?;
+    }
+    Ok(())
+}
+
+/// Journaled transactions in the order they were accepted.
+pub fn load_pending(storage: &Storage) -> Result<Vec<Transaction>, DbError> {
+    let mut pending = Vec::new();
+    for item in storage.rocksdb.iterator_cf(storage.cf_pending_txs(), IteratorMode::Start) {
+        let (_, value) = item?;
+        let (received_at, tx): (u64, Transaction) = bincode::deserialize(&value)?;
+        pending.push((received_at, tx));
+    }
+    pending.sort_by_key(|(received_at, _)| *received_at);
+    Ok(pending.into_iter().map(|(_, tx)| tx).collect())
+}
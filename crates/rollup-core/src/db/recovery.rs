@@ -1,9 +1,10 @@
+use super::error::DbError;
 use super::storage::Storage;
 use crate::types::BlockHeader;
 use chrono::Utc;
 use rocksdb::IteratorMode;
 
-pub async fn reconcile_databases_on_startup(storage: &Storage) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn reconcile_databases_on_startup(storage: &Storage) -> Result<(), DbError> {
     println!("\n--- Running Startup Recovery Check ---");
     let cf_batches = storage.cf_batches();
     let latest_rocksdb_id = storage.rocksdb.iterator_cf(cf_batches, IteratorMode::End)
@@ -22,11 +23,12 @@ pub async fn reconcile_databases_on_startup(storage: &Storage) -> Result<(), Box
         println!("  - Inconsistency detected! Reconciling SQLite...");
         for id in (latest_sqlite_id + 1)..=latest_rocksdb_id {
             if let Some(value) = storage.rocksdb.get_cf(cf_batches, id.to_be_bytes())? {
-                let header = BlockHeader::from_bytes(value.as_slice().try_into()?)?;
+                let header = BlockHeader::from_bytes(&value)
+                    .map_err(|e| DbError::Corrupt(format!("Invalid header of batch {}: {}", id, e)))?;
                 sqlx::query("INSERT OR REPLACE INTO batches (id, new_root, committed_at) VALUES (?, ?, ?)")
                     .bind(header.batch_id as i64)
-                    .bind(&header.new_root.to_vec())
-                    .bind(&Utc::now().to_rfc3339())
+                    .bind(header.new_root.to_vec())
+                    .bind(Utc::now().to_rfc3339())
                     .execute(&storage.sqlite)
                     .await?;
                 println!("    - Repaired batch {} in SQLite.", id);
@@ -1,4 +1,5 @@
-use rocksdb::{DB, Options, ColumnFamilyDescriptor};
+use super::error::DbError;
+use rocksdb::{DB, Options, ColumnFamilyDescriptor, FlushOptions};
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
 use std::{str::FromStr, sync::Arc};
 
@@ -10,15 +11,19 @@ pub struct Storage {
     cf_batches: *const rocksdb::ColumnFamily,
     cf_tx_by_sender: *const rocksdb::ColumnFamily,
     cf_tx_by_time: *const rocksdb::ColumnFamily,
+    cf_withdrawals: *const rocksdb::ColumnFamily,
+    cf_receipts: *const rocksdb::ColumnFamily,
+    cf_state_nodes: *const rocksdb::ColumnFamily,
+    cf_pending_txs: *const rocksdb::ColumnFamily,
 }
 
 unsafe impl Send for Storage {}
 unsafe impl Sync for Storage {}
 
-pub const CF_NAMES: &[&str] = &["accounts", "txs", "batches", "tx_by_sender", "tx_by_time"];
+pub const CF_NAMES: &[&str] = &["accounts", "txs", "batches", "tx_by_sender", "tx_by_time", "withdrawals", "receipts", "state_nodes", "pending_txs"];
 
 impl Storage {
-    pub async fn new(rocksdb_path: &str, sqlite_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(rocksdb_path: &str, sqlite_path: &str) -> Result<Self, DbError> {
         let mut db_opts = Options::default();
         db_opts.create_if_missing(true);
         db_opts.create_missing_column_families(true);
@@ -27,12 +32,16 @@ impl Storage {
         let cf_descriptors :Vec<_> = CF_NAMES.iter().map(|name| ColumnFamilyDescriptor::new(*name, Options::default())).collect();
         let db_arc = Arc::new(DB::open_cf_descriptors(&db_opts, rocksdb_path, cf_descriptors)?);
 
-        let (cf_accounts, cf_txs, cf_batches, cf_tx_by_sender, cf_tx_by_time);
+        let (cf_accounts, cf_txs, cf_batches, cf_tx_by_sender, cf_tx_by_time, cf_withdrawals, cf_receipts, cf_state_nodes, cf_pending_txs);
         { cf_accounts = db_arc.cf_handle("accounts").unwrap() as *const _; }
         { cf_txs = db_arc.cf_handle("txs").unwrap() as *const _; }
         { cf_batches = db_arc.cf_handle("batches").unwrap() as *const _; }
         { cf_tx_by_sender = db_arc.cf_handle("tx_by_sender").unwrap() as *const _; }
         { cf_tx_by_time = db_arc.cf_handle("tx_by_time").unwrap() as *const _; }
+        { cf_withdrawals = db_arc.cf_handle("withdrawals").unwrap() as *const _; }
+        { cf_receipts = db_arc.cf_handle("receipts").unwrap() as *const _; }
+        { cf_state_nodes = db_arc.cf_handle("state_nodes").unwrap() as *const _; }
+        { cf_pending_txs = db_arc.cf_handle("pending_txs").unwrap() as *const _; }
 
         let connect_options = SqliteConnectOptions::from_str(&format!("sqlite:{}", sqlite_path))?.create_if_missing(true);
         let pool = SqlitePoolOptions::new().connect_with(connect_options).await?;
@@ -48,7 +57,17 @@ impl Storage {
             );",
         ).execute(&pool).await?;
 
-        Ok(Self { rocksdb: db_arc, sqlite: pool, cf_accounts, cf_txs, cf_batches, cf_tx_by_sender, cf_tx_by_time })
+        Ok(Self { rocksdb: db_arc, sqlite: pool, cf_accounts, cf_txs, cf_batches, cf_tx_by_sender, cf_tx_by_time, cf_withdrawals, cf_receipts, cf_state_nodes, cf_pending_txs })
+    }
+
+    /// Flushes every column family and the WAL to disk and closes the SQLite
+    /// pool, checkpointing its WAL. Call once the sequencer has stopped.
+    pub async fn close(&self) -> Result<(), DbError> {
+        let cfs: Vec<_> = CF_NAMES.iter().filter_map(|name| self.rocksdb.cf_handle(name)).collect();
+        self.rocksdb.flush_cfs_opt(&cfs, &FlushOptions::default())?;
+        self.rocksdb.flush_wal(true)?;
+        self.sqlite.close().await;
+        Ok(())
     }
 
     #[inline] pub fn cf_accounts(&self) -> &rocksdb::ColumnFamily { unsafe { &*self.cf_accounts } }
@@ -56,5 +75,9 @@ impl Storage {
     #[inline] pub fn cf_batches(&self) -> &rocksdb::ColumnFamily { unsafe { &*self.cf_batches } }
     #[inline] pub fn cf_tx_by_sender(&self) -> &rocksdb::ColumnFamily { unsafe { &*self.cf_tx_by_sender } }
     #[inline] pub fn cf_tx_by_time(&self) -> &rocksdb::ColumnFamily { unsafe { &*self.cf_tx_by_time } }
+    #[inline] pub fn cf_withdrawals(&self) -> &rocksdb::ColumnFamily { unsafe { &*self.cf_withdrawals } }
+    #[inline] pub fn cf_receipts(&self) -> &rocksdb::ColumnFamily { unsafe { &*self.cf_receipts } }
+    #[inline] pub fn cf_state_nodes(&self) -> &rocksdb::ColumnFamily { unsafe { &*self.cf_state_nodes } }
+    #[inline] pub fn cf_pending_txs(&self) -> &rocksdb::ColumnFamily { unsafe { &*self.cf_pending_txs } }
 }
 
@@ -1,53 +1,327 @@
-use crate::db::Storage;
-use crate::types::{Account, Pubkey, Transaction, TransactionType};
-use std::collections::HashMap;
+use super::conflicts::partition;
+use super::error::{ExecutionError, SequencerError};
+use super::fees::FeeConfig;
+use crate::db::{DbError, Storage};
+use crate::types::{Account, AccountKey, BalanceChange, ChainId, Instruction, Pubkey, Receipt, ReceiptStatus, Transaction, TransactionType, TxHash, Withdrawal, MAX_INSTRUCTIONS, SYSTEM_SENDER};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
 
 pub struct BatchContext<'a> {
-    pub write_set: HashMap<Pubkey, Account>,
+    pub write_set: HashMap<AccountKey, Account>,
+    /// Withdrawals burned in this batch, in execution order.
+    pub withdrawals: Vec<Withdrawal>,
+    /// One receipt per executed transaction, in execution order.
+    pub receipts: Vec<Receipt>,
     storage: &'a Storage,
+    fees: FeeConfig,
+    chain_id: ChainId,
+    batch_id: u64,
+    /// Balances the current transaction has written, as they were before it ran.
+    touched: BTreeMap<AccountKey, u64>,
+    /// Previous write set entries for every write of the current transaction,
+    /// used to roll it back.
+    journal: Vec<(AccountKey, Option<Account>)>,
+    /// Net amount minted (deposits) minus burned (withdrawals) per mint.
+    issuance: BTreeMap<Pubkey, i128>,
+}
+
+/// Execution state to return to when part of a transaction fails.
+struct Checkpoint {
+    journal_len: usize,
+    withdrawals_len: usize,
+    touched: BTreeMap<AccountKey, u64>,
+    issuance: BTreeMap<Pubkey, i128>,
 }
 
 impl<'a> BatchContext<'a> {
-    pub fn new(storage: &'a Storage) -> Self {
-        Self { write_set: HashMap::new(), storage }
+    pub fn new(storage: &'a Storage, fees: FeeConfig, chain_id: ChainId, batch_id: u64) -> Self {
+        Self {
+            write_set: HashMap::new(),
+            withdrawals: Vec::new(),
+            receipts: Vec::new(),
+            storage,
+            fees,
+            chain_id,
+            batch_id,
+            touched: BTreeMap::new(),
+            journal: Vec::new(),
+            issuance: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_account(&self, key: &AccountKey) -> Result<Option<Account>, DbError> {
+        match self.write_set.get(key) {
+            Some(account) => Ok(Some(account.clone())),
+            None => self.stored_account(key),
+        }
+    }
+
+    /// The account as last committed, ignoring this batch's writes.
+    fn stored_account(&self, key: &AccountKey) -> Result<Option<Account>, DbError> {
+        match self.storage.rocksdb.get_cf(self.storage.cf_accounts(), key.to_bytes())? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Executes `tx` against the write set and records its receipt. A
+    /// rejected transaction leaves no trace in the write set; a failed one
+    /// keeps only its fee charge. On `ExecutionError::Storage` no receipt is
+    /// recorded and the write set may be partially updated, so the batch must
+    /// be discarded.
+    pub fn execute_transaction(&mut self, tx: &Transaction) -> Result<(), ExecutionError> {
+        self.touched.clear();
+        self.journal.clear();
+        let start = self.checkpoint();
+        let result = match self.validate_and_charge(tx) {
+            Err(e) => {
+                self.rollback(start);
+                Err((ReceiptStatus::Rejected, e))
+            }
+            Ok(()) => {
+                let charged = self.checkpoint();
+                self.execute_body(tx).map_err(|e| {
+                    self.rollback(charged);
+                    (ReceiptStatus::Failed, e)
+                })
+            }
+        };
+        if let Err((_, ExecutionError::Storage(_))) = result {
+            return result.map_err(|(_, e)| e);
+        }
+
+        let balance_changes = self.touched.iter()
+            .map(|(key, &before)| BalanceChange { key: *key, before, after: self.write_set[key].balance })
+            .filter(|change| change.before != change.after)
+            .collect();
+        let (status, error) = match &result {
+            Ok(()) => (ReceiptStatus::Success, None),
+            Err((status, e)) => (*status, Some(e)),
+        };
+        self.receipts.push(Receipt {
+            tx_hash: tx.hash(),
+            batch_id: self.batch_id,
+            index: self.receipts.len() as u32,
+            status,
+            error_code: error.map(|e| e.code().to_string()),
+            error_message: error.map(|e| e.to_string()),
+            balance_changes,
+        });
+        result.map_err(|(_, e)| e)
+    }
+
+    /// Executes `txs` in order, leaving the same write set, receipts and
+    /// withdrawals as calling `execute_transaction` on each. Transactions
+    /// that share no account run in parallel groups; a batch where any
+    /// transaction touches the fee collector's account itself, or whose fees
+    /// could overflow it, runs sequentially. Failures are recorded in the
+    /// receipts; an `Err` is a storage error, after which the batch must be
+    /// discarded.
+    pub fn execute_batch(&mut self, txs: &[Transaction]) -> Result<(), DbError> {
+        let collector_key = AccountKey::native(self.fees.collector);
+        let collector = self.get_account(&collector_key)?.unwrap_or_default();
+        let fees_fit = txs.iter().try_fold(collector.balance, |total, tx| total.checked_add(tx.fee)).is_some();
+        let groups = if self.write_set.is_empty() && fees_fit { partition(txs, &collector_key) } else { None };
+        let Some(groups) = groups.filter(|groups| groups.len() > 1) else {
+            for tx in txs {
+                if let Err(ExecutionError::Storage(e)) = self.execute_transaction(tx) { return Err(e); }
+            }
+            return Ok(());
+        };
+
+        let (storage, fees, chain_id, batch_id) = (self.storage, self.fees, self.chain_id, self.batch_id);
+        let executed = groups.par_iter().map(|group| {
+            let mut context = BatchContext::new(storage, fees, chain_id, batch_id);
+            let mut outcomes = Vec::with_capacity(group.len());
+            for &index in group {
+                if let Err(ExecutionError::Storage(e)) = context.execute_transaction(&txs[index]) { return Err(e); }
+                let receipt = context.receipts.pop().expect("executed transactions have a receipt");
+                outcomes.push((index, receipt, std::mem::take(&mut context.withdrawals)));
+            }
+            Ok((context.write_set, context.issuance, outcomes))
+        }).collect::<Result<Vec<_>, DbError>>()?;
+
+        let mut ordered: Vec<Option<(Receipt, Vec<Withdrawal>)>> = (0..txs.len()).map(|_| None).collect();
+        for (write_set, issuance, outcomes) in executed {
+            self.write_set.extend(write_set.into_iter().filter(|(key, _)| *key != collector_key));
+            for (mint, amount) in issuance {
+                *self.issuance.entry(mint).or_default() += amount;
+            }
+            for (index, receipt, withdrawals) in outcomes {
+                ordered[index] = Some((receipt, withdrawals));
+            }
+        }
+        // Each group credited its fees to the stored collector balance;
+        // replay the credits in batch order.
+        let mut collector_balance = collector.balance;
+        for (mut receipt, withdrawals) in ordered.into_iter().map(|outcome| outcome.expect("every transaction is in a group")) {
+            receipt.index = self.receipts.len() as u32;
+            for change in receipt.balance_changes.iter_mut().filter(|change| change.key == collector_key) {
+                let fee = change.after - change.before;
+                (change.before, change.after) = (collector_balance, collector_balance + fee);
+                collector_balance += fee;
+            }
+            self.withdrawals.extend(withdrawals);
+            self.receipts.push(receipt);
+        }
+        if collector_balance != collector.balance {
+            self.write_set.insert(collector_key, Account { balance: collector_balance, ..collector });
+        }
+        Ok(())
+    }
+
+    /// Number of receipts so far whose transaction was charged a fee.
+    pub fn included_count(&self) -> usize {
+        self.receipts.iter().filter(|receipt| receipt.status != ReceiptStatus::Rejected).count()
+    }
+
+    /// Checks that, for every mint, the balances this batch wrote changed by
+    /// exactly the amount deposited minus the amount withdrawn.
+    pub fn check_supply(&self) -> Result<(), SequencerError> {
+        let mut supply_delta: BTreeMap<Pubkey, i128> = BTreeMap::new();
+        for (key, account) in &self.write_set {
+            let before = self.stored_account(key)?.map_or(0, |account| account.balance);
+            *supply_delta.entry(key.mint).or_default() += i128::from(account.balance) - i128::from(before);
+        }
+        supply_delta.retain(|_, delta| *delta != 0);
+        let mut issuance = self.issuance.clone();
+        issuance.retain(|_, delta| *delta != 0);
+
+        if supply_delta != issuance {
+            return Err(SequencerError::InvariantViolation(format!(
+                "Batch {} changed supply by {:?} but issued {:?}",
+                self.batch_id,
+                supply_delta.iter().map(|(mint, delta)| (hex::encode(mint.0), *delta)).collect::<Vec<_>>(),
+                issuance.iter().map(|(mint, delta)| (hex::encode(mint.0), *delta)).collect::<Vec<_>>(),
+            )));
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            journal_len: self.journal.len(),
+            withdrawals_len: self.withdrawals.len(),
+            touched: self.touched.clone(),
+            issuance: self.issuance.clone(),
+        }
+    }
+
+    fn rollback(&mut self, checkpoint: Checkpoint) {
+        for (key, previous) in self.journal.drain(checkpoint.journal_len..).rev() {
+            match previous {
+                Some(account) => self.write_set.insert(key, account),
+                None => self.write_set.remove(&key),
+            };
+        }
+        self.withdrawals.truncate(checkpoint.withdrawals_len);
+        self.touched = checkpoint.touched;
+        self.issuance = checkpoint.issuance;
     }
 
-    pub fn get_account(&self, pubkey: &Pubkey) -> Option<Account> {
-        self.write_set.get(pubkey).cloned().or_else(|| {
-            self.storage.rocksdb.get_cf(self.storage.cf_accounts(), &pubkey.0)
-                .ok()
-                .flatten()
-                .and_then(|bytes| bincode::deserialize(&bytes).ok())
-        })
+    fn put_account(&mut self, key: AccountKey, account: Account) -> Result<(), DbError> {
+        if !self.touched.contains_key(&key) {
+            let before = self.get_account(&key)?.map_or(0, |account| account.balance);
+            self.touched.insert(key, before);
+        }
+        let previous = self.write_set.insert(key, account);
+        self.journal.push((key, previous));
+        Ok(())
     }
-    
-    pub fn execute_transaction(&mut self, tx: &Transaction) -> Result<(), String> {
+
+    /// Validates the signature and nonce, then debits the fee, credits the
+    /// collector and consumes the nonce. Runs before the transaction body, so
+    /// a failing body still pays. Deposits from `SYSTEM_SENDER` skip all of
+    /// this, since only the node itself submits them; deposits from anyone
+    /// else are rejected.
+    fn validate_and_charge(&mut self, tx: &Transaction) -> Result<(), ExecutionError> {
+        if tx.chain_id != self.chain_id { return Err(ExecutionError::WrongChain); }
+        if tx.sender == SYSTEM_SENDER {
+            return match tx.tx_type {
+                TransactionType::Deposit { .. } => Ok(()),
+                _ => Err(ExecutionError::InvalidSignature),
+            };
+        }
+        if let TransactionType::Deposit { .. } = tx.tx_type { return Err(ExecutionError::UnauthorizedDeposit); }
+        if !tx.verify_signature() { return Err(ExecutionError::InvalidSignature); }
+        if let Some(valid_until_batch) = tx.valid_until_batch
+            && self.batch_id > valid_until_batch
+        {
+            return Err(ExecutionError::Expired { valid_until_batch, batch_id: self.batch_id });
+        }
+        let sender_key = AccountKey::native(tx.sender);
+        let expected_nonce = self.get_account(&sender_key)?.map_or(0, |account| account.nonce);
+        if tx.nonce != expected_nonce {
+            return Err(ExecutionError::BadNonce { expected: expected_nonce, got: tx.nonce });
+        }
+        if tx.fee < self.fees.min_fee {
+            return Err(ExecutionError::FeeTooLow { fee: tx.fee, min_fee: self.fees.min_fee });
+        }
+        if let TransactionType::Multi { instructions } = &tx.tx_type
+            && !(1..=MAX_INSTRUCTIONS).contains(&instructions.len())
+        {
+            return Err(ExecutionError::InvalidInstructionCount { count: instructions.len() });
+        }
+        let mut sender = self.get_account(&sender_key)?.ok_or(ExecutionError::SenderNotFound)?;
+        sender.balance = sender.balance.checked_sub(tx.fee).ok_or(ExecutionError::InsufficientFunds)?;
+        sender.nonce = sender.nonce.checked_add(1).ok_or(ExecutionError::Overflow)?;
+        self.put_account(sender_key, sender)?;
+
+        if tx.fee > 0 {
+            self.credit(AccountKey::native(self.fees.collector), tx.fee)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the transaction body. Every write lands in the write set through
+    /// the journal, so `execute_transaction` can discard the whole body,
+    /// including earlier instructions of a `Multi`, when any step fails.
+    fn execute_body(&mut self, tx: &Transaction) -> Result<(), ExecutionError> {
         match tx.tx_type {
-            TransactionType::Transfer { amount } => self.execute_transfer(tx, amount),
-            TransactionType::Deposit { amount } => self.execute_deposit(tx, amount),
+            TransactionType::Transfer { amount, mint } => self.transfer(tx.sender, tx.recipient, amount, mint),
+            TransactionType::Deposit { amount, mint } => self.deposit(tx.recipient, amount, mint),
+            TransactionType::Withdraw { amount, l1_recipient, mint } => self.withdraw(tx.hash(), tx.sender, l1_recipient, amount, mint),
+            TransactionType::Multi { ref instructions } => {
+                let tx_hash = tx.hash();
+                for instruction in instructions {
+                    match *instruction {
+                        Instruction::Transfer { recipient, amount, mint } => self.transfer(tx.sender, recipient, amount, mint)?,
+                        Instruction::Withdraw { amount, l1_recipient, mint } => self.withdraw(tx_hash, tx.sender, l1_recipient, amount, mint)?,
+                    }
+                }
+                Ok(())
+            }
         }
     }
 
-    fn execute_transfer(&mut self, tx: &Transaction, amount: u64) -> Result<(), String> {
-        let mut sender = self.get_account(&tx.sender).ok_or("Sender not found")?;
-        let mut recipient = self.get_account(&tx.recipient).unwrap_or(Account { balance: 0, nonce: 0 });
+    fn debit(&mut self, key: AccountKey, amount: u64) -> Result<(), ExecutionError> {
+        let mut account = self.get_account(&key)?.ok_or(ExecutionError::SenderNotFound)?;
+        account.balance = account.balance.checked_sub(amount).ok_or(ExecutionError::InsufficientFunds)?;
+        Ok(self.put_account(key, account)?)
+    }
+
+    fn credit(&mut self, key: AccountKey, amount: u64) -> Result<(), ExecutionError> {
+        let mut account = self.get_account(&key)?.unwrap_or_default();
+        account.balance = account.balance.checked_add(amount).ok_or(ExecutionError::Overflow)?;
+        Ok(self.put_account(key, account)?)
+    }
 
-        if sender.balance < amount { return Err("Insufficient funds".to_string()); }
-        
-        sender.balance -= amount;
-        sender.nonce += 1;
-        recipient.balance += amount;
+    fn transfer(&mut self, sender: Pubkey, recipient: Pubkey, amount: u64, mint: Pubkey) -> Result<(), ExecutionError> {
+        self.debit(AccountKey::new(sender, mint), amount)?;
+        // Credit after the debit so a self-transfer sees the debited balance.
+        self.credit(AccountKey::new(recipient, mint), amount)
+    }
 
-        self.write_set.insert(tx.sender, sender);
-        self.write_set.insert(tx.recipient, recipient);
+    fn deposit(&mut self, recipient: Pubkey, amount: u64, mint: Pubkey) -> Result<(), ExecutionError> {
+        self.credit(AccountKey::new(recipient, mint), amount)?;
+        *self.issuance.entry(mint).or_default() += i128::from(amount);
         Ok(())
     }
-    
-    fn execute_deposit(&mut self, tx: &Transaction, amount: u64) -> Result<(), String> {
-        let mut recipient = self.get_account(&tx.recipient).unwrap_or(Account { balance: 0, nonce: 0 });
-        recipient.balance += amount;
-        self.write_set.insert(tx.recipient, recipient);
+
+    fn withdraw(&mut self, tx_hash: TxHash, sender: Pubkey, l1_recipient: Pubkey, amount: u64, mint: Pubkey) -> Result<(), ExecutionError> {
+        self.debit(AccountKey::new(sender, mint), amount)?;
+        *self.issuance.entry(mint).or_default() -= i128::from(amount);
+        self.withdrawals.push(Withdrawal { tx_hash, sender, l1_recipient, mint, amount });
         Ok(())
     }
 }
-
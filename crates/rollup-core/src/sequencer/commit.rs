@@ -1,5 +1,5 @@
-use crate::db::Storage;
-use crate::types::{Account,BlockHeader,Pubkey,Transaction};
+use crate::db::{DbError, Storage};
+use crate::types::{Account,AccountKey,BlockHeader,Receipt,Transaction,Withdrawal};
 use rocksdb::{WriteBatch,WriteOptions};
 use std::collections::HashMap;
 use chrono::Utc;
@@ -8,29 +8,49 @@ use chrono::Utc;
 pub async fn commit_batch(
     storage:&Storage,
     header: &BlockHeader,
-    write_set: &HashMap<Pubkey,Account>,
-    transactions : &[Transaction]
-) ->Result<(),Box<dyn std::error::Error>>{
+    write_set: &HashMap<AccountKey,Account>,
+    transactions : &[Transaction],
+    withdrawals: &[Withdrawal],
+    receipts: &[Receipt],
+    state_nodes: &[([u8; 32], Vec<u8>)]
+) ->Result<(),DbError>{
     let mut batch = WriteBatch::default();
     
-    for (pubkey,account) in write_set{
-        batch.put_cf(storage.cf_accounts(), &pubkey.0, bincode::serialize(account)?);
+    for (key,account) in write_set{
+        batch.put_cf(storage.cf_accounts(), key.to_bytes(), bincode::serialize(account)?);
     }
 
     for tx in transactions {
         let timestamp = Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64;
-        batch.put_cf(storage.cf_txs(), &tx.signature.0, bincode::serialize(tx)?);
+        let tx_hash = tx.hash();
+        batch.put_cf(storage.cf_txs(), tx_hash.0, bincode::serialize(tx)?);
         
         let mut time_key = Vec::with_capacity(8+32);
         time_key.extend_from_slice(&timestamp.to_be_bytes());
-        time_key.extend_from_slice(&tx.signature.0);
-        batch.put_cf(storage.cf_tx_by_time(), time_key, &[]);
+        time_key.extend_from_slice(&tx_hash.0);
+        batch.put_cf(storage.cf_tx_by_time(), time_key, []);
 
         let mut sender_key = Vec::with_capacity(32+8+32);
         sender_key.extend_from_slice(&tx.sender.0);
         sender_key.extend_from_slice(&timestamp.to_be_bytes());
-        sender_key.extend_from_slice(&tx.signature.0);
-        batch.put_cf(storage.cf_tx_by_sender(), sender_key, &[]);
+        sender_key.extend_from_slice(&tx_hash.0);
+        batch.put_cf(storage.cf_tx_by_sender(), sender_key, []);
+        batch.delete_cf(storage.cf_pending_txs(), tx_hash.0);
+    }
+
+    for (index, withdrawal) in withdrawals.iter().enumerate() {
+        let mut key = Vec::with_capacity(8+4);
+        key.extend_from_slice(&header.batch_id.to_be_bytes());
+        key.extend_from_slice(&(index as u32).to_be_bytes());
+        batch.put_cf(storage.cf_withdrawals(), key, bincode::serialize(withdrawal)?);
+    }
+
+    for receipt in receipts {
+        batch.put_cf(storage.cf_receipts(), receipt.tx_hash.0, bincode::serialize(receipt)?);
+    }
+
+    for (hash, node) in state_nodes {
+        batch.put_cf(storage.cf_state_nodes(), hash, node);
     }
 
     batch.put_cf(storage.cf_batches(), header.batch_id.to_be_bytes(), header.to_bytes()?);
@@ -41,8 +61,8 @@ pub async fn commit_batch(
     
     sqlx::query("INSERT OR REPLACE INTO batches (id, new_root, committed_at) VALUES (?, ?, ?)")
         .bind(header.batch_id as i64)
-        .bind(&header.new_root.to_vec())
-        .bind(&Utc::now().to_rfc3339())
+        .bind(header.new_root.to_vec())
+        .bind(Utc::now().to_rfc3339())
         .execute(&storage.sqlite)
         .await?;
         
@@ -1,38 +1,169 @@
-use crate::types::{Account,Pubkey};
+use crate::types::{Account, AccountKey, Pubkey};
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonHasher};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
 
-fn pseudo_poseidon_hash(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
-    let mut result = [0u8; 32];
-    for i in 0..32 { result[i] = a[i] ^ b[i]; }
-    result
+/// Domain tag of AccountsFoldHashV1, read as a big-endian integer. At 23
+/// bytes it is below the BN254 scalar modulus, so the field element is exact.
+pub const ACCOUNTS_FOLD_DOMAIN: &[u8] = b"zelana:accounts-fold:v1";
+
+/// Circom-compatible Poseidon (x^5 S-box, BN254 scalar field) hashers for the
+/// arities the state commitments use.
+pub(super) struct Hasher {
+    pair: Poseidon<Fr>,
+    triple: Poseidon<Fr>,
+    quad: Poseidon<Fr>,
+}
+
+impl Hasher {
+    pub(super) fn new() -> Self {
+        Self {
+            pair: Poseidon::<Fr>::new_circom(2).expect("circom parameters exist for 2 inputs"),
+            triple: Poseidon::<Fr>::new_circom(3).expect("circom parameters exist for 3 inputs"),
+            quad: Poseidon::<Fr>::new_circom(4).expect("circom parameters exist for 4 inputs"),
+        }
+    }
+
+    fn hash2(&mut self, a: Fr, b: Fr) -> Fr {
+        self.pair.hash(&[a, b]).expect("input count matches the hasher width")
+    }
+
+    fn hash3(&mut self, a: Fr, b: Fr, c: Fr) -> Fr {
+        self.triple.hash(&[a, b, c]).expect("input count matches the hasher width")
+    }
+
+    fn hash4(&mut self, inputs: [Fr; 4]) -> Fr {
+        self.quad.hash(&inputs).expect("input count matches the hasher width")
+    }
+
+    /// `H(owner_hi, owner_lo, mint_hi, mint_lo)`.
+    fn key_hash(&mut self, key: &AccountKey) -> Fr {
+        let [owner_hi, owner_lo] = pubkey_limbs(&key.owner);
+        let [mint_hi, mint_lo] = pubkey_limbs(&key.mint);
+        self.hash4([owner_hi, owner_lo, mint_hi, mint_lo])
+    }
+
+    /// Fold leaf: `H(key_hash, H(balance, nonce))`.
+    fn fold_leaf(&mut self, key: &AccountKey, account: &Account) -> Fr {
+        let key_hash = self.key_hash(key);
+        let inner_hash = self.hash2(Fr::from(account.balance), Fr::from(account.nonce));
+        self.hash2(key_hash, inner_hash)
+    }
+
+    /// The key hash as big-endian bytes; its bits are the key's path in the state tree.
+    pub(super) fn key_path(&mut self, key: &AccountKey) -> [u8; 32] {
+        let key_hash = self.key_hash(key);
+        to_bytes(key_hash)
+    }
+
+    /// State tree leaf: `H(key_hash, balance, nonce)`.
+    pub(super) fn tree_leaf(&mut self, key: &AccountKey, account: &Account) -> [u8; 32] {
+        let key_hash = self.key_hash(key);
+        to_bytes(self.hash3(key_hash, Fr::from(account.balance), Fr::from(account.nonce)))
+    }
+
+    /// State tree internal node: `H(left, right)`.
+    pub(super) fn tree_node(&mut self, left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        to_bytes(self.hash2(Fr::from_be_bytes_mod_order(&left), Fr::from_be_bytes_mod_order(&right)))
+    }
 }
 
-/// Computes the AccountsFoldHashV1 state commitment.
+/// The two-input `H` of both state commitments, on big-endian field
+/// elements: a state tree internal node is `poseidon2(left, right)`.
+pub fn poseidon2(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+    Hasher::new().tree_node(a, b)
+}
+
+fn to_bytes(value: Fr) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&value.into_bigint().to_bytes_be());
+    out
+}
+
+/// Splits a key into its high and low 16 bytes. A full 32-byte key can exceed
+/// the field modulus, so reducing it directly would let two keys collide.
+fn pubkey_limbs(key: &Pubkey) -> [Fr; 2] {
+    [Fr::from_be_bytes_mod_order(&key.0[..16]), Fr::from_be_bytes_mod_order(&key.0[16..])]
+}
+
+/// Computes the AccountsFoldHashV1 state commitment:
+///
+/// ```text
+/// state_0 = H(domain, batch_id)
+/// key     = H(owner_hi, owner_lo, mint_hi, mint_lo)
+/// leaf    = H(key, H(balance, nonce))
+/// state_i = H(state_{i-1}, leaf_i)            accounts in AccountKey order
+/// root    = H(state_n, n)
+/// ```
+///
+/// `H` is circom's Poseidon over the BN254 scalar field, integers are
+/// embedded as field elements directly and the root is returned as 32
+/// big-endian bytes. `H(1, 2)` is
+/// `115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a`, as in
+/// circomlib's Poseidon test. Reference roots:
+///
+/// | accounts (owner, mint, balance, nonce)                  | batch | root |
+/// | ------------------------------------------------------- | ----- | ---- |
+/// | none                                                    | 0     | `28e9c9aa46be44d58018c70fe44df00067e67328035620a6d178c51c62728ce1` |
+/// | `[1; 32]`, native, 1000, 0                              | 1     | `11faad1ae630956af366c2b738ce03781eb4c8b1d8fb8a3c914c3cb0bbd43f1d` |
+/// | the above, `[2; 32]`, `[3; 32]`, 500, 7 and `[0xff; 32]`, native, `u64::MAX`, `u64::MAX` | 42 | `20dfd1b66fdc92960c120fa009856ad6ce342b394e16b2b2d0397453fc120958` |
+///
+/// These and `H(1, 2)` are checked by `state_commitment_test_vectors` in the
+/// rpc_server integration tests.
 pub fn compute_state_commitment(
-    accounts: &BTreeMap<Pubkey, Account>,
+    accounts: &BTreeMap<AccountKey, Account>,
+    batch_id: u64,
+) -> [u8; 32] {
+    let mut hasher = Hasher::new();
+    let leaves: Vec<Fr> = accounts.iter().map(|(key, account)| hasher.fold_leaf(key, account)).collect();
+    fold(&mut hasher, &leaves, batch_id)
+}
+
+/// `compute_state_commitment` with the leaves hashed on the rayon pool, for
+/// recomputing a root from scratch. Each step of the fold takes the one
+/// before it, so only the leaves, three of the four hashes per account, can
+/// be spread across threads; the chain is folded on the calling thread.
+/// Returns the same root as the sequential version.
+pub fn compute_state_commitment_parallel(
+    accounts: &BTreeMap<AccountKey, Account>,
     batch_id: u64,
 ) -> [u8; 32] {
-    let mut ds = [0u8; 32];
-    ds[0..23].copy_from_slice(b"zelana:accounts-fold:v1");
-    let mut batch_id_bytes = [0u8; 32];
-    batch_id_bytes[..8].copy_from_slice(&batch_id.to_le_bytes());
-    
-    let mut current_state = pseudo_poseidon_hash(ds, batch_id_bytes);
-
-    for (pubkey, account) in accounts {
-        let mut balance_bytes = [0u8; 32];
-        balance_bytes[..8].copy_from_slice(&account.balance.to_le_bytes());
-        let mut nonce_bytes = [0u8; 32];
-        nonce_bytes[..8].copy_from_slice(&account.nonce.to_le_bytes());
-        
-        let inner_hash = pseudo_poseidon_hash(balance_bytes, nonce_bytes);
-        let leaf_hash = pseudo_poseidon_hash(pubkey.0, inner_hash);
-        
-        current_state = pseudo_poseidon_hash(current_state, leaf_hash);
-    }
-    
-    let mut count_bytes = [0u8; 32];
-    count_bytes[..8].copy_from_slice(&(accounts.len() as u64).to_le_bytes());
-    
-    pseudo_poseidon_hash(current_state, count_bytes)
+    let leaves: Vec<Fr> = accounts.par_iter()
+        .map_init(Hasher::new, |hasher, (key, account)| hasher.fold_leaf(key, account))
+        .collect();
+    fold(&mut Hasher::new(), &leaves, batch_id)
+}
+
+/// Chains fold leaves, in account order, into the root.
+fn fold(hasher: &mut Hasher, leaves: &[Fr], batch_id: u64) -> [u8; 32] {
+    let domain = Fr::from_be_bytes_mod_order(ACCOUNTS_FOLD_DOMAIN);
+    let mut current_state = hasher.hash2(domain, Fr::from(batch_id));
+    for leaf_hash in leaves {
+        current_state = hasher.hash2(current_state, *leaf_hash);
+    }
+    to_bytes(hasher.hash2(current_state, Fr::from(leaves.len() as u64)))
+}
+
+/// Binary SHA-256 Merkle root over `leaves`, used for a header's `tx_root`
+/// and `receipts_root`. Leaves are hashed as `sha256(0x00 || leaf)` and
+/// nodes as `sha256(0x01 || left || right)`; an odd node at the end of a
+/// level moves up unchanged. The root of no leaves is all zeros.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() { return [0; 32]; }
+    let mut level: Vec<[u8; 32]> = leaves.iter()
+        .map(|leaf| Sha256::new().chain_update([0]).chain_update(leaf).finalize().into())
+        .collect();
+    while level.len() > 1 {
+        level = level.chunks(2)
+            .map(|pair| match pair {
+                [left, right] => Sha256::new().chain_update([1]).chain_update(left).chain_update(right).finalize().into(),
+                [node] => *node,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
 }
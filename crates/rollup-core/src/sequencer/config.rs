@@ -0,0 +1,54 @@
+use super::error::SequencerError;
+use std::time::Duration;
+
+/// Limits on the batches `RollupCore` seals and on its mempool. A batch is
+/// sealed as soon as the pending transactions reach any one of the size
+/// limits, or once the oldest has waited `seal_timeout`; transactions past a
+/// limit wait for the next batch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SequencerConfig {
+    pub max_txs_per_batch: usize,
+    /// Total bincode-serialized size of a batch's transactions.
+    pub max_batch_bytes: usize,
+    /// Total `Transaction::compute_units` of a batch.
+    pub max_batch_compute: u64,
+    pub seal_timeout: Duration,
+    /// Most transactions the mempool holds; beyond it the lowest-fee ones
+    /// are evicted.
+    pub max_pending_txs: usize,
+    /// Most transactions the mempool holds per sender.
+    pub max_pending_per_sender: usize,
+}
+
+impl SequencerConfig {
+    /// Fails unless every limit is above zero. A zero limit would seal empty
+    /// batches or none at all, and a zero `seal_timeout` is not a valid
+    /// interval.
+    pub fn validate(&self) -> Result<(), SequencerError> {
+        let limits = [
+            ("max_txs_per_batch", self.max_txs_per_batch as u128),
+            ("max_batch_bytes", self.max_batch_bytes as u128),
+            ("max_batch_compute", u128::from(self.max_batch_compute)),
+            ("seal_timeout", self.seal_timeout.as_millis()),
+            ("max_pending_txs", self.max_pending_txs as u128),
+            ("max_pending_per_sender", self.max_pending_per_sender as u128),
+        ];
+        match limits.iter().find(|(_, value)| *value == 0) {
+            Some((name, _)) => Err(SequencerError::InvalidConfig(format!("{} must be greater than zero", name))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for SequencerConfig {
+    fn default() -> Self {
+        Self {
+            max_txs_per_batch: 1000,
+            max_batch_bytes: 1 << 20,
+            max_batch_compute: 10_000,
+            seal_timeout: Duration::from_millis(500),
+            max_pending_txs: 10_000,
+            max_pending_per_sender: 64,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::types::{AccountKey, Instruction, Transaction, TransactionType, SYSTEM_SENDER};
+use std::collections::HashMap;
+
+/// Every account `tx` may read or write, apart from the fee collector's
+/// credit. A superset: a transaction rejected early touches fewer.
+pub fn access_set(tx: &Transaction) -> Vec<AccountKey> {
+    let mut keys = Vec::new();
+    if tx.sender != SYSTEM_SENDER {
+        keys.push(AccountKey::native(tx.sender));
+    }
+    match tx.tx_type {
+        TransactionType::Transfer { mint, .. } => {
+            keys.push(AccountKey::new(tx.sender, mint));
+            keys.push(AccountKey::new(tx.recipient, mint));
+        }
+        TransactionType::Deposit { mint, .. } => keys.push(AccountKey::new(tx.recipient, mint)),
+        TransactionType::Withdraw { mint, .. } => keys.push(AccountKey::new(tx.sender, mint)),
+        TransactionType::Multi { ref instructions } => {
+            for instruction in instructions {
+                match *instruction {
+                    Instruction::Transfer { recipient, mint, .. } => {
+                        keys.push(AccountKey::new(tx.sender, mint));
+                        keys.push(AccountKey::new(recipient, mint));
+                    }
+                    Instruction::Withdraw { mint, .. } => keys.push(AccountKey::new(tx.sender, mint)),
+                }
+            }
+        }
+    }
+    keys
+}
+
+/// Splits `txs` into groups that share no account in their `access_set`s.
+/// Each group lists indices into `txs` in ascending order, and groups are
+/// ordered by their first index. `None` if a transaction touches `excluded`
+/// itself, since every fee-paying transaction writes it.
+pub fn partition(txs: &[Transaction], excluded: &AccountKey) -> Option<Vec<Vec<usize>>> {
+    let mut parent: Vec<usize> = (0..txs.len()).collect();
+    let mut owner: HashMap<AccountKey, usize> = HashMap::new();
+    for (index, tx) in txs.iter().enumerate() {
+        for key in access_set(tx) {
+            if key == *excluded { return None; }
+            match owner.get(&key) {
+                Some(&other) => union(&mut parent, index, other),
+                None => { owner.insert(key, index); }
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root = HashMap::new();
+    for index in 0..txs.len() {
+        let root = find(&mut parent, index);
+        let group = *group_of_root.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(index);
+    }
+    Some(groups)
+}
+
+fn find(parent: &mut [usize], mut index: usize) -> usize {
+    while parent[index] != index {
+        parent[index] = parent[parent[index]];
+        index = parent[index];
+    }
+    index
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    if a != b { parent[a.max(b)] = a.min(b); }
+}
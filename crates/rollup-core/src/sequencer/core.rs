@@ -1,87 +1,292 @@
-use super::{batch::BatchContext, commit::commit_batch, commitment::compute_state_commitment};
-use crate::db::Storage;
-use crate::types::{Account, BlockHeader, Transaction};
+use super::{batch::BatchContext, commit::commit_batch, commitment::merkle_root, config::SequencerConfig, error::SequencerError, fees::FeeConfig, mempool::{Mempool, MempoolError}, scheme::{CommitmentScheme, StateCommitment}};
+use crate::db::{forget_pending, load_pending, DbError, Storage};
+use crate::types::{Account, AccountKey, BlockHeader, ChainId, Pubkey, Receipt, ReceiptStatus, Transaction, SYSTEM_SENDER};
 use rocksdb::IteratorMode;
-use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::{mpsc::Receiver, oneshot};
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
 
-const MAX_TX_PER_BATCH: usize = 5;
+/// A transaction for `RollupCore::run`. If `reply` is set, it receives the
+/// mempool's verdict once the transaction is queued or turned away.
+#[derive(Debug)]
+pub struct Submission {
+    pub tx: Transaction,
+    pub reply: Option<oneshot::Sender<Result<(), MempoolError>>>,
+}
+
+impl From<Transaction> for Submission {
+    fn from(tx: Transaction) -> Self {
+        Self { tx, reply: None }
+    }
+}
 
 pub struct RollupCore {
     storage: Arc<Storage>,
-    tx_receiver: Receiver<Transaction>,
-    mempool: Vec<Transaction>,
+    tx_receiver: Receiver<Submission>,
+    mempool: Mempool,
     tip: BlockHeader,
+    commitment: Box<dyn StateCommitment>,
+    fees: FeeConfig,
+    chain_id: ChainId,
+    check_invariants: bool,
+    config: SequencerConfig,
 }
 
 impl RollupCore {
-    pub async fn new(storage: Arc<Storage>, tx_receiver: Receiver<Transaction>) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(storage: Arc<Storage>, tx_receiver: Receiver<Submission>, config: SequencerConfig, fees: FeeConfig, chain_id: ChainId) -> Result<Self, SequencerError> {
+        if fees.collector == SYSTEM_SENDER {
+            return Err(SequencerError::InvalidConfig("Fee collector cannot be the system sender".into()));
+        }
+        config.validate()?;
         let tip = Self::load_tip(storage.as_ref()).await?;
-        Ok(Self { storage, tx_receiver, mempool: Vec::new(), tip })
+        let scheme = Self::recorded_scheme(&tip)?.unwrap_or_default();
+        let mempool = Self::restore_mempool(storage.as_ref(), &config)?;
+        Ok(Self {
+            storage,
+            tx_receiver,
+            mempool,
+            tip,
+            commitment: scheme.build(),
+            fees,
+            chain_id,
+            check_invariants: cfg!(debug_assertions),
+            config,
+        })
     }
 
-    async fn load_tip(storage: &Storage) -> Result<BlockHeader, Box<dyn std::error::Error>> {
-        Ok(storage.rocksdb.iterator_cf(storage.cf_batches(), IteratorMode::End)
-            .next()
-            .and_then(|res| res.ok())
-            .map(|(_, val)| BlockHeader::from_bytes(val.as_ref().try_into().unwrap()).unwrap())
-            .unwrap_or_else(BlockHeader::genesis))
+    /// Enables or disables the per-batch supply check run before commit.
+    /// On by default in debug builds.
+    pub fn with_invariant_checks(mut self, enabled: bool) -> Self {
+        self.check_invariants = enabled;
+        self
+    }
+
+    /// Replaces the state commitment, by default the scheme of the tip, or
+    /// `CommitmentScheme::SparseMerkle` at genesis. The scheme is recorded in
+    /// every header sealed from here on, so a chain cannot switch schemes: it
+    /// must match the tip's.
+    pub fn with_commitment(mut self, commitment: Box<dyn StateCommitment>) -> Result<Self, SequencerError> {
+        if let Some(recorded) = Self::recorded_scheme(&self.tip)?
+            && recorded != commitment.scheme()
+        {
+            return Err(SequencerError::InvalidConfig(format!("Chain is committed with {}, not {}", recorded, commitment.scheme())));
+        }
+        self.commitment = commitment;
+        Ok(self)
+    }
+
+    /// The scheme the tip was sealed with; `None` at genesis.
+    fn recorded_scheme(tip: &BlockHeader) -> Result<Option<CommitmentScheme>, SequencerError> {
+        if tip.batch_id == 0 { return Ok(None); }
+        CommitmentScheme::from_flags(tip.flags)
+            .map(Some)
+            .ok_or_else(|| DbError::Corrupt(format!("Unknown commitment scheme in the flags of batch {}", tip.batch_id)).into())
+    }
+
+    async fn load_tip(storage: &Storage) -> Result<BlockHeader, SequencerError> {
+        match storage.rocksdb.iterator_cf(storage.cf_batches(), IteratorMode::End).next() {
+            Some(item) => {
+                let (_, value) = item?;
+                Ok(BlockHeader::from_bytes(&value).map_err(|e| DbError::Corrupt(format!("Invalid header in DB: {}", e)))?)
+            }
+            None => Ok(BlockHeader::genesis()),
+        }
+    }
+
+    /// Rebuilds the mempool from the transactions journaled by
+    /// `journal_pending` that no batch has included yet.
+    fn restore_mempool(storage: &Storage, config: &SequencerConfig) -> Result<Mempool, SequencerError> {
+        let mut mempool = Mempool::new(config);
+        let pending = load_pending(storage)?;
+        let mut discarded = Vec::new();
+        for tx in pending {
+            let tx_hash = tx.hash();
+            if let Err(e) = mempool.insert(tx) {
+                eprintln!("[Core] Discarding journaled tx {}: {}", hex::encode(tx_hash.0), e);
+                discarded.push(tx_hash);
+            }
+        }
+        discarded.extend(mempool.take_dropped());
+        forget_pending(storage, discarded)?;
+        if !mempool.is_empty() {
+            println!("[Core] Restored {} pending txs from the journal.", mempool.len());
+        }
+        Ok(mempool)
     }
 
     pub async fn run(mut self) {
-        println!("[Core] RollupCore started. Tip is at batch {}.", self.tip.batch_id);
-        while let Some(tx) = self.tx_receiver.recv().await {
-            self.mempool.push(tx);
-            if self.mempool.len() >= MAX_TX_PER_BATCH {
-                if let Err(e) = self.seal_and_commit_batch().await {
-                    eprintln!("[Core] Failed to seal batch: {}", e);
+        println!("[Core] RollupCore started. Tip is at batch {}, committed with {}.", self.tip.batch_id, self.commitment.scheme());
+        // Restarted when a transaction arrives at an empty mempool, so it
+        // fires `seal_timeout` after the oldest pending one.
+        let seal_timeout = self.config.seal_timeout;
+        let mut seal_timer = interval_at(Instant::now() + seal_timeout, seal_timeout);
+        seal_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        self.seal_full_batches().await;
+        loop {
+            tokio::select! {
+                received = self.tx_receiver.recv() => {
+                    let Some(Submission { tx, reply }) = received else { break };
+                    if self.mempool.is_empty() { seal_timer.reset(); }
+                    let tx_hash = tx.hash();
+                    let mut discarded = Vec::new();
+                    let admitted = self.mempool.insert(tx);
+                    if let Err(e) = &admitted {
+                        eprintln!("[Core] Rejected tx {}: {}", hex::encode(tx_hash.0), e);
+                        // A duplicate's journal entry belongs to the pending copy.
+                        if *e != MempoolError::Duplicate { discarded.push(tx_hash); }
+                    }
+                    discarded.extend(self.mempool.take_dropped());
+                    if let Err(e) = forget_pending(&self.storage, discarded) {
+                        eprintln!("[Core] Failed to update the pending journal: {}", e);
+                    }
+                    // The submitter may have given up waiting.
+                    if let Some(reply) = reply { let _ = reply.send(admitted); }
+                    self.seal_full_batches().await;
+                }
+                _ = seal_timer.tick() => {
+                    if !self.mempool.is_empty()
+                        && let Err(e) = self.seal_and_commit_batch().await
+                    {
+                        eprintln!("[Core] Failed to seal batch on timeout: {}", e);
+                    }
                 }
             }
         }
-        if !self.mempool.is_empty() {
+        while !self.mempool.is_empty() {
+            let pending = self.mempool.len();
             if let Err(e) = self.seal_and_commit_batch().await {
-                 eprintln!("[Core] Failed to seal final batch: {}", e);
+                eprintln!("[Core] Failed to seal final batch: {}", e);
+                break;
+            }
+            if self.mempool.len() >= pending {
+                eprintln!("[Core] Leaving {} txs waiting on nonce gaps in the journal.", pending);
+                break;
             }
         }
         println!("[Core] Transaction channel closed. Shutting down.");
     }
 
-    async fn seal_and_commit_batch(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let txs_to_process = std::mem::take(&mut self.mempool);
-        println!("[Core] Sealing batch {} with {} txs.", self.tip.batch_id + 1, txs_to_process.len());
-
-        let mut batch_context = BatchContext::new(&self.storage);
-        for tx in &txs_to_process {
-            if let Err(e) = batch_context.execute_transaction(tx) {
-                eprintln!("[Core] Tx failed: {:?}, Error: {}", tx.signature, e);
+    /// Seals batches while the pending transactions fill one.
+    async fn seal_full_batches(&mut self) {
+        while self.batch_is_full() {
+            let pending = self.mempool.len();
+            if let Err(e) = self.seal_and_commit_batch().await {
+                eprintln!("[Core] Failed to seal batch: {}", e);
+                break;
             }
+            // The rest wait on nonce gaps.
+            if self.mempool.len() >= pending { break; }
         }
-        
-        let mut all_accounts = BTreeMap::new();
-        let iter = self.storage.rocksdb.iterator_cf(self.storage.cf_accounts(), IteratorMode::Start);
-        for item in iter {
-            let (key, value) = item?;
-            all_accounts.insert(bincode::deserialize(&key)?, bincode::deserialize(&value)?);
+    }
+
+    /// Whether the pending transactions reach any of the batch size limits.
+    fn batch_is_full(&self) -> bool {
+        self.mempool.len() >= self.config.max_txs_per_batch
+            || self.mempool.bytes() >= self.config.max_batch_bytes
+            || self.mempool.compute_units() >= self.config.max_batch_compute
+    }
+
+    /// Next nonce `sender` may use, from its committed native account.
+    fn committed_nonce(storage: &Storage, sender: &Pubkey) -> Result<u64, DbError> {
+        match storage.rocksdb.get_cf(storage.cf_accounts(), AccountKey::native(*sender).to_bytes())? {
+            Some(bytes) => Ok(bincode::deserialize::<Account>(&bytes)?.nonce),
+            None => Ok(0),
         }
-        all_accounts.extend(batch_context.write_set.clone());
+    }
 
+    async fn seal_and_commit_batch(&mut self) -> Result<(), SequencerError> {
+        let storage = Arc::clone(&self.storage);
+        let mut txs_to_process = self.mempool.select(&self.config, |sender| Self::committed_nonce(&storage, sender))?;
+        if let Err(e) = self.seal_selected(&mut txs_to_process).await {
+            // Keep the transactions for the next attempt. If only the SQLite
+            // write failed, RocksDB holds the batch: the tip follows it, and
+            // the transactions it included are dropped as replays.
+            self.requeue(txs_to_process)?;
+            self.tip = Self::load_tip(&self.storage).await?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Executes and commits `txs`, taken from the mempool by `select`, as the
+    /// next batch.
+    async fn seal_selected(&mut self, txs_to_process: &mut Vec<Transaction>) -> Result<(), SequencerError> {
+        forget_pending(&self.storage, self.mempool.take_dropped())?;
+        self.drop_already_included(txs_to_process)?;
+        if txs_to_process.is_empty() { return Ok(()); }
         let new_batch_id = self.tip.batch_id + 1;
-        let new_root = compute_state_commitment(&all_accounts, new_batch_id);
+        println!("[Core] Sealing batch {} with {} txs.", new_batch_id, txs_to_process.len());
+
+        let mut batch_context = BatchContext::new(&self.storage, self.fees, self.chain_id, new_batch_id);
+        batch_context.execute_batch(txs_to_process)?;
+        for receipt in &batch_context.receipts {
+            if let Some(error) = &receipt.error_message {
+                eprintln!("[Core] Tx failed: {}, Error: {}", hex::encode(receipt.tx_hash.0), error);
+            }
+        }
+        if self.check_invariants {
+            batch_context.check_supply()?;
+        }
         
+        let commitment_update = self.commitment.update(&self.storage, &self.tip, &batch_context.write_set)?;
+
         let header = BlockHeader {
             batch_id: new_batch_id,
             prev_root: self.tip.new_root,
-            new_root,
-            tx_count: txs_to_process.len() as u32,
+            new_root: commitment_update.root,
+            tx_count: batch_context.included_count() as u32,
             open_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            flags: self.commitment.scheme().to_flags(),
+            tx_root: merkle_root(&txs_to_process.iter().map(|tx| tx.hash().0).collect::<Vec<_>>()),
+            receipts_root: merkle_root(&batch_context.receipts.iter().map(Receipt::hash).collect::<Vec<_>>()),
             ..BlockHeader::genesis()
         };
         
-        commit_batch(&self.storage, &header, &batch_context.write_set, &txs_to_process).await?;
+        commit_batch(&self.storage, &header, &batch_context.write_set, txs_to_process, &batch_context.withdrawals, &batch_context.receipts, &commitment_update.nodes).await?;
         self.tip = header;
         Ok(())
     }
-}
 
+    /// Puts transactions a failed seal took from the mempool back, and
+    /// forgets any it no longer accepts.
+    fn requeue(&mut self, txs: Vec<Transaction>) -> Result<(), SequencerError> {
+        let mut discarded = Vec::new();
+        for tx in txs {
+            let tx_hash = tx.hash();
+            if self.mempool.insert(tx).is_err() { discarded.push(tx_hash); }
+        }
+        discarded.extend(self.mempool.take_dropped());
+        forget_pending(&self.storage, discarded)?;
+        Ok(())
+    }
+
+    /// Drops duplicates within `txs` and replays of transactions an earlier
+    /// batch already charged, so their receipts are never overwritten.
+    /// Rejected transactions may be resubmitted.
+    fn drop_already_included(&self, txs: &mut Vec<Transaction>) -> Result<(), SequencerError> {
+        let mut seen = HashSet::new();
+        let mut replays = Vec::new();
+        let mut kept = Vec::with_capacity(txs.len());
+        for tx in txs.drain(..) {
+            let tx_hash = tx.hash();
+            if !seen.insert(tx_hash) {
+                eprintln!("[Core] Dropping duplicate tx {}", hex::encode(tx_hash.0));
+                continue;
+            }
+            if let Some(bytes) = self.storage.rocksdb.get_cf(self.storage.cf_receipts(), tx_hash.0)? {
+                let receipt: Receipt = bincode::deserialize(&bytes)?;
+                if receipt.status != ReceiptStatus::Rejected {
+                    eprintln!("[Core] Dropping replay of tx {} from batch {}", hex::encode(tx_hash.0), receipt.batch_id);
+                    replays.push(tx_hash);
+                    continue;
+                }
+            }
+            kept.push(tx);
+        }
+        *txs = kept;
+        forget_pending(&self.storage, replays)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use crate::db::DbError;
+use std::fmt;
+use std::time::SystemTimeError;
+
+/// Why `BatchContext::execute_transaction` did not apply a transaction.
+#[derive(Debug)]
+pub enum ExecutionError {
+    /// Signed for another chain.
+    WrongChain,
+    InvalidSignature,
+    /// A `Deposit` from anyone but `SYSTEM_SENDER`, which would mint funds.
+    UnauthorizedDeposit,
+    BadNonce { expected: u64, got: u64 },
+    /// The batch being built is past the transaction's `valid_until_batch`.
+    Expired { valid_until_batch: u64, batch_id: u64 },
+    FeeTooLow { fee: u64, min_fee: u64 },
+    SenderNotFound,
+    InsufficientFunds,
+    /// A `Multi` transaction with no instructions or more than `MAX_INSTRUCTIONS`.
+    InvalidInstructionCount { count: usize },
+    /// A balance would exceed `u64::MAX`.
+    Overflow,
+    /// Account state could not be read. Not the transaction's fault, so the
+    /// batch is aborted instead of recording a receipt.
+    Storage(DbError),
+}
+
+impl ExecutionError {
+    /// Stable machine-readable code, stored in receipts.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExecutionError::WrongChain => "wrong_chain",
+            ExecutionError::InvalidSignature => "invalid_signature",
+            ExecutionError::UnauthorizedDeposit => "unauthorized_deposit",
+            ExecutionError::BadNonce { .. } => "bad_nonce",
+            ExecutionError::Expired { .. } => "expired",
+            ExecutionError::FeeTooLow { .. } => "fee_too_low",
+            ExecutionError::SenderNotFound => "sender_not_found",
+            ExecutionError::InsufficientFunds => "insufficient_funds",
+            ExecutionError::InvalidInstructionCount { .. } => "invalid_instruction_count",
+            ExecutionError::Overflow => "overflow",
+            ExecutionError::Storage(_) => "storage_error",
+        }
+    }
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::WrongChain => write!(f, "Transaction was signed for another chain"),
+            ExecutionError::InvalidSignature => write!(f, "Invalid signature"),
+            ExecutionError::UnauthorizedDeposit => write!(f, "Only the system sender can deposit"),
+            ExecutionError::BadNonce { expected, got } => write!(f, "Bad nonce: expected {}, got {}", expected, got),
+            ExecutionError::Expired { valid_until_batch, batch_id } => {
+                write!(f, "Expired: valid until batch {}, current batch is {}", valid_until_batch, batch_id)
+            }
+            ExecutionError::FeeTooLow { fee, min_fee } => write!(f, "Fee {} below minimum {}", fee, min_fee),
+            ExecutionError::SenderNotFound => write!(f, "Sender not found"),
+            ExecutionError::InsufficientFunds => write!(f, "Insufficient funds"),
+            ExecutionError::InvalidInstructionCount { count } => write!(f, "Invalid instruction count {}", count),
+            ExecutionError::Overflow => write!(f, "Balance overflow"),
+            ExecutionError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExecutionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExecutionError::Storage(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DbError> for ExecutionError {
+    fn from(e: DbError) -> Self { ExecutionError::Storage(e) }
+}
+
+/// Why `RollupCore` could not start or seal a batch.
+#[derive(Debug)]
+pub enum SequencerError {
+    Storage(DbError),
+    Clock(SystemTimeError),
+    /// A post-execution consistency check failed; the batch was not committed.
+    InvariantViolation(String),
+    /// The sequencer was started with settings it cannot run with.
+    InvalidConfig(String),
+}
+
+impl fmt::Display for SequencerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequencerError::Storage(e) => write!(f, "{}", e),
+            SequencerError::Clock(e) => write!(f, "System clock error: {}", e),
+            SequencerError::InvariantViolation(msg) => write!(f, "Invariant violated: {}", msg),
+            SequencerError::InvalidConfig(msg) => write!(f, "Invalid sequencer config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SequencerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SequencerError::Storage(e) => Some(e),
+            SequencerError::Clock(e) => Some(e),
+            SequencerError::InvariantViolation(_) | SequencerError::InvalidConfig(_) => None,
+        }
+    }
+}
+
+impl From<DbError> for SequencerError {
+    fn from(e: DbError) -> Self { SequencerError::Storage(e) }
+}
+
+impl From<rocksdb::Error> for SequencerError {
+    fn from(e: rocksdb::Error) -> Self { SequencerError::Storage(e.into()) }
+}
+
+impl From<bincode::Error> for SequencerError {
+    fn from(e: bincode::Error) -> Self { SequencerError::Storage(e.into()) }
+}
+
+impl From<SystemTimeError> for SequencerError {
+    fn from(e: SystemTimeError) -> Self { SequencerError::Clock(e) }
+}
@@ -0,0 +1,12 @@
+use crate::types::Pubkey;
+
+/// Fee policy applied by `BatchContext` to every transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeConfig {
+    /// Account credited with every fee charged. `RollupCore::new` refuses
+    /// `SYSTEM_SENDER`, which can only send deposits, so its fees could
+    /// never be spent.
+    pub collector: Pubkey,
+    /// Smallest `Transaction::fee` the sequencer accepts.
+    pub min_fee: u64,
+}
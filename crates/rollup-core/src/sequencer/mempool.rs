@@ -0,0 +1,254 @@
+use super::config::SequencerConfig;
+use crate::db::DbError;
+use crate::types::{Pubkey, Transaction, TxHash, SYSTEM_SENDER};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fmt;
+
+/// Why `Mempool::insert` turned a transaction away.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MempoolError {
+    /// The same transaction is already pending.
+    Duplicate,
+    /// Another transaction holds the sender's nonce and pays at least as much.
+    Underpriced,
+    /// The sender already has `max_pending_per_sender` transactions queued
+    /// below this nonce.
+    SenderFull,
+    /// The pool is at `max_pending_txs` and nothing pending pays less.
+    Full,
+}
+
+impl MempoolError {
+    /// Stable machine-readable code, returned to RPC clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MempoolError::Duplicate => "duplicate",
+            MempoolError::Underpriced => "underpriced",
+            MempoolError::SenderFull => "sender_full",
+            MempoolError::Full => "mempool_full",
+        }
+    }
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::Duplicate => write!(f, "Transaction is already pending"),
+            MempoolError::Underpriced => write!(f, "A pending transaction with this nonce pays an equal or higher fee"),
+            MempoolError::SenderFull => write!(f, "Too many pending transactions from this sender"),
+            MempoolError::Full => write!(f, "Mempool is full"),
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+struct Pending {
+    tx: Transaction,
+    hash: TxHash,
+    /// Arrival order, used to break fee ties.
+    seq: u64,
+    bytes: usize,
+}
+
+/// Transactions waiting for a batch.
+///
+/// Each sender's transactions are queued by nonce, so a sender's next batch
+/// always starts at its committed nonce; a transaction past a gap waits
+/// until the gap is filled. Across senders, `select` takes the highest fee
+/// first. System deposits skip the nonce queue and wait in arrival order.
+pub struct Mempool {
+    max_txs: usize,
+    max_per_sender: usize,
+    /// Per sender, keyed by nonce, or by arrival for `SYSTEM_SENDER`.
+    senders: HashMap<Pubkey, BTreeMap<u64, Pending>>,
+    by_hash: HashMap<TxHash, (Pubkey, u64)>,
+    next_seq: u64,
+    bytes: usize,
+    compute: u64,
+    /// Hashes of transactions replaced, evicted or dropped since the last
+    /// `take_dropped`.
+    dropped: Vec<TxHash>,
+}
+
+impl Mempool {
+    pub fn new(config: &SequencerConfig) -> Self {
+        Self {
+            max_txs: config.max_pending_txs,
+            max_per_sender: config.max_pending_per_sender,
+            senders: HashMap::new(),
+            by_hash: HashMap::new(),
+            next_seq: 0,
+            bytes: 0,
+            compute: 0,
+            dropped: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+
+    /// Total serialized size of the pending transactions.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Total `Transaction::compute_units` of the pending transactions.
+    pub fn compute_units(&self) -> u64 {
+        self.compute
+    }
+
+    pub fn contains(&self, hash: &TxHash) -> bool {
+        self.by_hash.contains_key(hash)
+    }
+
+    /// Queues `tx`. A transaction for a nonce that is already pending
+    /// replaces it if it pays a higher fee. When the sender or the pool is at
+    /// its cap, the transaction furthest from execution, the sender's highest
+    /// nonce or the pool's lowest-fee tail, is evicted to make room if `tx`
+    /// ranks above it.
+    pub fn insert(&mut self, tx: Transaction) -> Result<(), MempoolError> {
+        let hash = tx.hash();
+        if self.by_hash.contains_key(&hash) { return Err(MempoolError::Duplicate); }
+        let sender = tx.sender;
+        let slot = if sender == SYSTEM_SENDER { self.next_seq } else { tx.nonce };
+
+        if sender != SYSTEM_SENDER {
+            let queue = self.senders.get(&sender);
+            if let Some(existing) = queue.and_then(|queue| queue.get(&slot)) {
+                if tx.fee <= existing.tx.fee { return Err(MempoolError::Underpriced); }
+                self.drop_slot(&sender, slot);
+            } else if let Some(queue) = queue
+                && queue.len() >= self.max_per_sender
+            {
+                let (&highest, _) = queue.last_key_value().expect("a full queue is not empty");
+                if slot > highest { return Err(MempoolError::SenderFull); }
+                self.drop_slot(&sender, highest);
+            }
+        }
+
+        if self.len() >= self.max_txs {
+            // Deposits are never evicted and always outrank user transactions.
+            let victim = self.senders.iter()
+                .filter(|(owner, _)| **owner != SYSTEM_SENDER)
+                .filter_map(|(owner, queue)| queue.last_key_value().map(|(slot, pending)| (pending.tx.fee, Reverse(pending.seq), *owner, *slot)))
+                .min();
+            match victim {
+                Some((fee, _, owner, victim_slot)) if sender == SYSTEM_SENDER || tx.fee > fee => {
+                    self.drop_slot(&owner, victim_slot);
+                }
+                _ => return Err(MempoolError::Full),
+            }
+        }
+
+        let bytes = bincode::serialized_size(&tx).expect("transactions always serialize") as usize;
+        self.bytes += bytes;
+        self.compute += tx.compute_units();
+        self.by_hash.insert(hash, (sender, slot));
+        self.senders.entry(sender).or_default().insert(slot, Pending { tx, hash, seq: self.next_seq, bytes });
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    /// Removes and returns the best set of transactions for the next batch
+    /// within `limits`: the highest-fee sender queue head first, each
+    /// sender's transactions in nonce order from `next_nonce(sender)`, its
+    /// committed nonce. Transactions below the committed nonce, or too large
+    /// for any batch, are dropped.
+    pub fn select(
+        &mut self,
+        limits: &SequencerConfig,
+        mut next_nonce: impl FnMut(&Pubkey) -> Result<u64, DbError>,
+    ) -> Result<Vec<Transaction>, DbError> {
+        let mut stale = Vec::new();
+        let mut heads = BinaryHeap::new();
+        for (sender, queue) in &self.senders {
+            let first = if *sender == SYSTEM_SENDER {
+                queue.keys().next().copied()
+            } else {
+                let next = next_nonce(sender)?;
+                stale.extend(queue.range(..next).map(|(slot, _)| (*sender, *slot)));
+                queue.contains_key(&next).then_some(next)
+            };
+            if let Some(slot) = first {
+                heads.push(self.rank(sender, slot));
+            }
+        }
+        for (sender, slot) in stale {
+            if let Some(tx) = self.drop_slot(&sender, slot) {
+                eprintln!("[Mempool] Dropping tx {}: nonce {} is already used", hex::encode(tx.hash().0), tx.nonce);
+            }
+        }
+
+        let mut batch = Vec::new();
+        let (mut bytes, mut compute) = (0, 0);
+        while let Some((_, _, sender, slot)) = heads.pop() {
+            if batch.len() >= limits.max_txs_per_batch.max(1) { break; }
+            let pending = &self.senders[&sender][&slot];
+            let (tx_bytes, tx_compute) = (pending.bytes, pending.tx.compute_units());
+            if tx_bytes > limits.max_batch_bytes || tx_compute > limits.max_batch_compute {
+                let tx = self.drop_slot(&sender, slot).expect("slot is pending");
+                eprintln!("[Mempool] Dropping tx {}: {} bytes and {} compute units exceed the batch limits", hex::encode(tx.hash().0), tx_bytes, tx_compute);
+                // Later nonces now have a gap; later deposits can still go.
+                if sender == SYSTEM_SENDER && let Some(next) = self.next_slot(&sender, slot) {
+                    heads.push(self.rank(&sender, next));
+                }
+                continue;
+            }
+            // This sender's queue waits for the next batch; smaller
+            // transactions from other senders may still fit.
+            if bytes + tx_bytes > limits.max_batch_bytes || compute + tx_compute > limits.max_batch_compute { continue; }
+
+            bytes += tx_bytes;
+            compute += tx_compute;
+            let next = if sender == SYSTEM_SENDER {
+                self.next_slot(&sender, slot)
+            } else {
+                slot.checked_add(1).filter(|next| self.senders[&sender].contains_key(next))
+            };
+            batch.push(self.remove_slot(&sender, slot).expect("slot is pending"));
+            if let Some(next) = next {
+                heads.push(self.rank(&sender, next));
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Hashes of the transactions `insert` replaced or evicted and `select`
+    /// dropped since the last call.
+    pub fn take_dropped(&mut self) -> Vec<TxHash> {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Priority of a queued transaction: highest fee, then earliest arrival.
+    fn rank(&self, sender: &Pubkey, slot: u64) -> (u64, Reverse<u64>, Pubkey, u64) {
+        let pending = &self.senders[sender][&slot];
+        (pending.tx.fee, Reverse(pending.seq), *sender, slot)
+    }
+
+    fn next_slot(&self, sender: &Pubkey, slot: u64) -> Option<u64> {
+        self.senders.get(sender)?.range(slot + 1..).next().map(|(next, _)| *next)
+    }
+
+    fn drop_slot(&mut self, sender: &Pubkey, slot: u64) -> Option<Transaction> {
+        let tx = self.remove_slot(sender, slot)?;
+        self.dropped.push(tx.hash());
+        Some(tx)
+    }
+
+    fn remove_slot(&mut self, sender: &Pubkey, slot: u64) -> Option<Transaction> {
+        let queue = self.senders.get_mut(sender)?;
+        let pending = queue.remove(&slot)?;
+        if queue.is_empty() { self.senders.remove(sender); }
+        self.by_hash.remove(&pending.hash);
+        self.bytes -= pending.bytes;
+        self.compute -= pending.tx.compute_units();
+        Some(pending.tx)
+    }
+}
@@ -1,10 +1,24 @@
 mod batch;
 mod commit;
 mod commitment;
+mod config;
+mod conflicts;
 mod core;
+mod error;
+mod fees;
+mod mempool;
+mod scheme;
+mod smt;
 
 pub use batch::BatchContext;
 pub use commit::commit_batch;
-pub use commitment::compute_state_commitment;
-pub use core::RollupCore;
+pub use commitment::{compute_state_commitment, compute_state_commitment_parallel, merkle_root, poseidon2};
+pub use config::SequencerConfig;
+pub use conflicts::{access_set, partition};
+pub use core::{RollupCore, Submission};
+pub use error::{ExecutionError, SequencerError};
+pub use fees::FeeConfig;
+pub use mempool::{Mempool, MempoolError};
+pub use scheme::{AccountsFold, CommitmentScheme, CommitmentUpdate, SparseMerkle, StateCommitment, StateProof};
+pub use smt::{AccountProof, MerkleProof, StateNode, StateTree, TreeUpdate, EMPTY_ROOT, TREE_DEPTH};
 
@@ -0,0 +1,232 @@
+use super::commitment::{compute_state_commitment, compute_state_commitment_parallel};
+use super::smt::{MerkleProof, StateTree, EMPTY_ROOT};
+use crate::db::{DbError, Storage};
+use crate::types::{Account, AccountKey, BlockHeader, FLAGS_COMMITMENT_MASK};
+use rocksdb::{IteratorMode, WriteBatch};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Which `StateCommitment` produced a header's `new_root`, stored in the low
+/// byte of `BlockHeader::flags`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommitmentScheme {
+    /// `StateTree`. Id 0, so headers written before schemes were recorded
+    /// read as the tree they were built with.
+    #[default]
+    SparseMerkle,
+    /// `compute_state_commitment` over every account.
+    AccountsFold,
+}
+
+impl CommitmentScheme {
+    pub fn id(self) -> u8 {
+        match self {
+            CommitmentScheme::SparseMerkle => 0,
+            CommitmentScheme::AccountsFold => 1,
+        }
+    }
+
+    /// The scheme recorded in a header's flags, or `None` for an unknown id.
+    pub fn from_flags(flags: u32) -> Option<Self> {
+        match flags & FLAGS_COMMITMENT_MASK {
+            0 => Some(CommitmentScheme::SparseMerkle),
+            1 => Some(CommitmentScheme::AccountsFold),
+            _ => None,
+        }
+    }
+
+    pub fn to_flags(self) -> u32 {
+        u32::from(self.id())
+    }
+
+    /// A fresh commitment of this scheme, for `RollupCore::with_commitment`.
+    pub fn build(self) -> Box<dyn StateCommitment> {
+        match self {
+            CommitmentScheme::SparseMerkle => Box::new(SparseMerkle::default()),
+            CommitmentScheme::AccountsFold => Box::new(AccountsFold),
+        }
+    }
+}
+
+impl fmt::Display for CommitmentScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CommitmentScheme::SparseMerkle => "smt",
+            CommitmentScheme::AccountsFold => "fold",
+        })
+    }
+}
+
+impl FromStr for CommitmentScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "smt" => Ok(CommitmentScheme::SparseMerkle),
+            "fold" => Ok(CommitmentScheme::AccountsFold),
+            other => Err(format!("Unknown state commitment scheme {:?} (expected \"smt\" or \"fold\")", other)),
+        }
+    }
+}
+
+/// A new root and the data to store with the batch that commits it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommitmentUpdate {
+    pub root: [u8; 32],
+    /// Written to `cf_state_nodes` under their hashes. Their encoding is up
+    /// to the scheme.
+    pub nodes: Vec<([u8; 32], Vec<u8>)>,
+}
+
+/// Opening of a root at one key, as produced by `StateCommitment::prove`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StateProof {
+    Merkle(MerkleProof),
+    /// Every account at the batch; the verifier refolds them.
+    Fold(Vec<(AccountKey, Account)>),
+}
+
+impl StateProof {
+    pub fn scheme(&self) -> CommitmentScheme {
+        match self {
+            StateProof::Merkle(_) => CommitmentScheme::SparseMerkle,
+            StateProof::Fold(_) => CommitmentScheme::AccountsFold,
+        }
+    }
+
+    /// Checks that `header.new_root` holds `account` under `key`, or no
+    /// account when `account` is `None`. Fails if the header's flags name a
+    /// different scheme than the proof's.
+    pub fn verify(&self, header: &BlockHeader, key: &AccountKey, account: Option<&Account>) -> bool {
+        if CommitmentScheme::from_flags(header.flags) != Some(self.scheme()) { return false; }
+        match self {
+            StateProof::Merkle(proof) => proof.verify(&header.new_root, key, account),
+            StateProof::Fold(accounts) => {
+                let folded: BTreeMap<_, _> = accounts.iter().cloned().collect();
+                folded.len() == accounts.len()
+                    && folded.get(key) == account
+                    && compute_state_commitment(&folded, header.batch_id) == header.new_root
+            }
+        }
+    }
+}
+
+/// A commitment to the account state, chosen per chain with
+/// `RollupCore::with_commitment`. `RollupCore` asks it for the root of each
+/// batch it seals; everything else about the scheme stays behind this trait.
+pub trait StateCommitment: Send {
+    fn scheme(&self) -> CommitmentScheme;
+
+    /// Root over the committed accounts, whose latest batch is `tip`.
+    fn root(&mut self, storage: &Storage, tip: &BlockHeader) -> Result<[u8; 32], DbError>;
+
+    /// Root after `changes` are committed as the batch following `tip`.
+    /// Nothing is persisted until the returned nodes are committed.
+    fn update(&mut self, storage: &Storage, tip: &BlockHeader, changes: &HashMap<AccountKey, Account>) -> Result<CommitmentUpdate, DbError>;
+
+    /// Opens `header.new_root` at `key`: the account stored there, if any,
+    /// and a proof of it. `None` if this scheme cannot open that root.
+    fn prove(&self, storage: &Storage, header: &BlockHeader, key: &AccountKey) -> Result<Option<(Option<Account>, StateProof)>, DbError>;
+}
+
+/// The `StateTree`. Updates touch only the changed paths and every committed
+/// root stays provable.
+#[derive(Default)]
+pub struct SparseMerkle {
+    /// Root built from a full account scan, for the batch it was built at.
+    bootstrapped: Option<(u64, [u8; 32])>,
+}
+
+impl StateCommitment for SparseMerkle {
+    fn scheme(&self) -> CommitmentScheme {
+        CommitmentScheme::SparseMerkle
+    }
+
+    /// Batches only update the tree with what they change, so accounts that
+    /// exist before the first batch, or a tip committed before the tree
+    /// existed, need the tree built from a full account scan once here.
+    fn root(&mut self, storage: &Storage, tip: &BlockHeader) -> Result<[u8; 32], DbError> {
+        let tree = StateTree::new(storage);
+        if tip.batch_id > 0 && tree.contains(&tip.new_root)? {
+            return Ok(tip.new_root);
+        }
+        if let Some((batch_id, root)) = self.bootstrapped
+            && batch_id == tip.batch_id
+        {
+            return Ok(root);
+        }
+
+        let accounts = load_accounts(storage)?;
+        let root = if accounts.is_empty() {
+            EMPTY_ROOT
+        } else {
+            println!("[Core] Building state tree from {} accounts.", accounts.len());
+            let update = StateTree::from_accounts(&accounts);
+            let mut batch = WriteBatch::default();
+            for (hash, node) in &update.nodes {
+                batch.put_cf(storage.cf_state_nodes(), hash, bincode::serialize(node)?);
+            }
+            storage.rocksdb.write(batch)?;
+            update.root
+        };
+        self.bootstrapped = Some((tip.batch_id, root));
+        Ok(root)
+    }
+
+    fn update(&mut self, storage: &Storage, tip: &BlockHeader, changes: &HashMap<AccountKey, Account>) -> Result<CommitmentUpdate, DbError> {
+        let root = self.root(storage, tip)?;
+        let update = StateTree::new(storage).update(&root, changes)?;
+        let nodes = update.nodes.iter()
+            .map(|(hash, node)| Ok((*hash, bincode::serialize(node)?)))
+            .collect::<Result<_, DbError>>()?;
+        Ok(CommitmentUpdate { root: update.root, nodes })
+    }
+
+    fn prove(&self, storage: &Storage, header: &BlockHeader, key: &AccountKey) -> Result<Option<(Option<Account>, StateProof)>, DbError> {
+        let mut tree = StateTree::new(storage);
+        if !tree.contains(&header.new_root)? { return Ok(None); }
+        let (account, proof) = tree.prove(&header.new_root, key)?;
+        Ok(Some((account, StateProof::Merkle(proof))))
+    }
+}
+
+/// `compute_state_commitment`, the AccountsFoldHashV1 fold. It has no
+/// incremental form: every update refolds all accounts, and only the latest
+/// batch can be proven, since older account states are not kept.
+pub struct AccountsFold;
+
+impl StateCommitment for AccountsFold {
+    fn scheme(&self) -> CommitmentScheme {
+        CommitmentScheme::AccountsFold
+    }
+
+    fn root(&mut self, storage: &Storage, tip: &BlockHeader) -> Result<[u8; 32], DbError> {
+        if tip.batch_id > 0 { return Ok(tip.new_root); }
+        Ok(compute_state_commitment_parallel(&load_accounts(storage)?, tip.batch_id))
+    }
+
+    fn update(&mut self, storage: &Storage, tip: &BlockHeader, changes: &HashMap<AccountKey, Account>) -> Result<CommitmentUpdate, DbError> {
+        let mut accounts = load_accounts(storage)?;
+        accounts.extend(changes.iter().map(|(key, account)| (*key, account.clone())));
+        Ok(CommitmentUpdate { root: compute_state_commitment_parallel(&accounts, tip.batch_id + 1), nodes: Vec::new() })
+    }
+
+    fn prove(&self, storage: &Storage, header: &BlockHeader, key: &AccountKey) -> Result<Option<(Option<Account>, StateProof)>, DbError> {
+        let accounts = load_accounts(storage)?;
+        if compute_state_commitment_parallel(&accounts, header.batch_id) != header.new_root { return Ok(None); }
+        let account = accounts.get(key).cloned();
+        Ok(Some((account, StateProof::Fold(accounts.into_iter().collect()))))
+    }
+}
+
+fn load_accounts(storage: &Storage) -> Result<BTreeMap<AccountKey, Account>, DbError> {
+    let mut accounts = BTreeMap::new();
+    for item in storage.rocksdb.iterator_cf(storage.cf_accounts(), IteratorMode::Start) {
+        let (key, value) = item?;
+        let key = AccountKey::from_bytes(&key).ok_or_else(|| DbError::Corrupt("Invalid account key in DB".into()))?;
+        accounts.insert(key, bincode::deserialize(&value)?);
+    }
+    Ok(accounts)
+}
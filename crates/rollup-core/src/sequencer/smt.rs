@@ -0,0 +1,277 @@
+use super::commitment::Hasher;
+use super::scheme::CommitmentScheme;
+use crate::db::{DbError, Storage};
+use crate::types::{Account, AccountKey, BlockHeader};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Hash of an empty subtree, and so the root of a tree with no accounts.
+pub const EMPTY_ROOT: [u8; 32] = [0; 32];
+
+/// Maximum path length: one level per bit of a key hash.
+pub const TREE_DEPTH: usize = 256;
+
+/// Subtrees with at most this many accounts are built on a single thread by
+/// `StateTree::from_accounts`.
+const PARALLEL_SUBTREE_SIZE: usize = 1024;
+
+/// A state tree node, stored in `cf_state_nodes` under its hash.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StateNode {
+    Internal { left: [u8; 32], right: [u8; 32] },
+    Leaf { key: AccountKey, account: Account },
+}
+
+/// Nodes written by `StateTree::update`, to be persisted with the batch.
+pub struct TreeUpdate {
+    pub root: [u8; 32],
+    pub nodes: Vec<([u8; 32], StateNode)>,
+}
+
+/// Sparse Merkle tree over all accounts, stored in RocksDB.
+///
+/// An account's path is the big-endian bits of `H(owner_hi, owner_lo,
+/// mint_hi, mint_lo)`, most significant first, with 0 going left. A leaf
+/// hashes to `H(key_hash, balance, nonce)`, an internal node to
+/// `H(left, right)` and an empty subtree is `EMPTY_ROOT`, where `H` is the
+/// same Poseidon as `compute_state_commitment`. A subtree holding a single
+/// account is just that account's leaf, so a path only goes as deep as
+/// needed to tell it apart from its neighbours.
+///
+/// Nodes are addressed by hash and never deleted, so the root of every
+/// committed batch can still be opened.
+pub struct StateTree<'a> {
+    storage: &'a Storage,
+    hasher: Hasher,
+}
+
+/// Where `key` sits in the tree: the siblings along its path and the leaf
+/// the path ends at, if any.
+struct Walk {
+    siblings: Vec<[u8; 32]>,
+    leaf: Option<(AccountKey, Account)>,
+}
+
+/// An account's path in a pending update.
+#[derive(Clone, Copy)]
+struct Entry {
+    path: [u8; 32],
+    hash: [u8; 32],
+}
+
+impl<'a> StateTree<'a> {
+    pub fn new(storage: &'a Storage) -> Self {
+        Self { storage, hasher: Hasher::new() }
+    }
+
+    /// Whether `root` is the empty tree or a node this tree has stored.
+    pub fn contains(&self, root: &[u8; 32]) -> Result<bool, DbError> {
+        Ok(*root == EMPTY_ROOT || self.storage.rocksdb.get_cf(self.storage.cf_state_nodes(), root)?.is_some())
+    }
+
+    /// The account stored under `key` in the tree with the given root.
+    pub fn get(&mut self, root: &[u8; 32], key: &AccountKey) -> Result<Option<Account>, DbError> {
+        let walk = self.walk(root, key)?;
+        Ok(walk.leaf.filter(|(leaf_key, _)| leaf_key == key).map(|(_, account)| account))
+    }
+
+    /// Opens the tree with the given root at `key`: the account stored there,
+    /// if any, and a proof of it that `MerkleProof::verify` accepts.
+    pub fn prove(&mut self, root: &[u8; 32], key: &AccountKey) -> Result<(Option<Account>, MerkleProof), DbError> {
+        let Walk { siblings, leaf } = self.walk(root, key)?;
+        Ok(match leaf {
+            Some((leaf_key, account)) if leaf_key == *key => (Some(account), MerkleProof { siblings, other_leaf: None }),
+            other_leaf => (None, MerkleProof { siblings, other_leaf }),
+        })
+    }
+
+    /// Writes `accounts` into the tree with the given root. Returns the new
+    /// root and the nodes it adds; nothing is persisted until they are
+    /// committed.
+    pub fn update<'b>(&mut self, root: &[u8; 32], accounts: impl IntoIterator<Item = (&'b AccountKey, &'b Account)>) -> Result<TreeUpdate, DbError> {
+        let mut nodes = Vec::new();
+        let mut entries: Vec<Entry> = accounts.into_iter()
+            .map(|(key, account)| {
+                let entry = Entry { path: self.hasher.key_path(key), hash: self.hasher.tree_leaf(key, account) };
+                nodes.push((entry.hash, StateNode::Leaf { key: *key, account: account.clone() }));
+                entry
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.path);
+
+        let root = self.insert(*root, 0, &entries, &mut nodes)?;
+        Ok(TreeUpdate { root, nodes })
+    }
+
+    /// Builds a tree holding exactly `accounts` from scratch, as at genesis
+    /// or when re-syncing, hashing leaves and disjoint subtrees of the key
+    /// space on the rayon pool. Gives the same root and nodes as `update`
+    /// from `EMPTY_ROOT`.
+    pub fn from_accounts(accounts: &BTreeMap<AccountKey, Account>) -> TreeUpdate {
+        let (mut entries, leaves): (Vec<Entry>, Vec<([u8; 32], StateNode)>) = accounts.par_iter()
+            .map_init(Hasher::new, |hasher, (key, account)| {
+                let entry = Entry { path: hasher.key_path(key), hash: hasher.tree_leaf(key, account) };
+                (entry, (entry.hash, StateNode::Leaf { key: *key, account: account.clone() }))
+            })
+            .unzip();
+        entries.par_sort_unstable_by_key(|entry| entry.path);
+
+        let (root, internal_nodes) = build_parallel(0, &entries);
+        let mut nodes = leaves;
+        nodes.extend(internal_nodes);
+        TreeUpdate { root, nodes }
+    }
+
+    fn node(&self, hash: &[u8; 32]) -> Result<StateNode, DbError> {
+        let bytes = self.storage.rocksdb.get_cf(self.storage.cf_state_nodes(), hash)?
+            .ok_or_else(|| DbError::Corrupt(format!("Missing state node {}", hex::encode(hash))))?;
+        Ok(bincode::deserialize(&bytes)?)
+    }
+
+    fn walk(&mut self, root: &[u8; 32], key: &AccountKey) -> Result<Walk, DbError> {
+        let path = self.hasher.key_path(key);
+        let mut siblings = Vec::new();
+        let mut hash = *root;
+        while hash != EMPTY_ROOT {
+            match self.node(&hash)? {
+                StateNode::Leaf { key, account } => return Ok(Walk { siblings, leaf: Some((key, account)) }),
+                StateNode::Internal { .. } if siblings.len() == TREE_DEPTH => {
+                    return Err(DbError::Corrupt(format!("State tree below {} is deeper than {} levels", hex::encode(root), TREE_DEPTH)));
+                }
+                StateNode::Internal { left, right } => {
+                    let (next, sibling) = if bit(&path, siblings.len()) { (right, left) } else { (left, right) };
+                    siblings.push(sibling);
+                    hash = next;
+                }
+            }
+        }
+        Ok(Walk { siblings, leaf: None })
+    }
+
+    /// Inserts `entries`, sorted by path, into the stored subtree `hash` at `depth`.
+    fn insert(&mut self, hash: [u8; 32], depth: usize, entries: &[Entry], nodes: &mut Vec<([u8; 32], StateNode)>) -> Result<[u8; 32], DbError> {
+        if entries.is_empty() { return Ok(hash); }
+        if hash == EMPTY_ROOT { return Ok(build(&mut self.hasher, depth, entries, nodes)); }
+        match self.node(&hash)? {
+            StateNode::Internal { left, right } => {
+                let split = entries.partition_point(|entry| !bit(&entry.path, depth));
+                let left = self.insert(left, depth + 1, &entries[..split], nodes)?;
+                let right = self.insert(right, depth + 1, &entries[split..], nodes)?;
+                Ok(internal(&mut self.hasher, left, right, nodes))
+            }
+            StateNode::Leaf { key, .. } => {
+                // The existing account stays unless one of the entries replaces it.
+                let path = self.hasher.key_path(&key);
+                let mut merged = entries.to_vec();
+                if let Err(i) = entries.binary_search_by_key(&path, |entry| entry.path) {
+                    merged.insert(i, Entry { path, hash });
+                }
+                Ok(build(&mut self.hasher, depth, &merged, nodes))
+            }
+        }
+    }
+}
+
+/// Builds the subtree at `depth` holding exactly `entries`, sorted by path.
+fn build(hasher: &mut Hasher, depth: usize, entries: &[Entry], nodes: &mut Vec<([u8; 32], StateNode)>) -> [u8; 32] {
+    match entries {
+        [] => EMPTY_ROOT,
+        [entry] => entry.hash,
+        _ => {
+            let split = entries.partition_point(|entry| !bit(&entry.path, depth));
+            let left = build(hasher, depth + 1, &entries[..split], nodes);
+            let right = build(hasher, depth + 1, &entries[split..], nodes);
+            internal(hasher, left, right, nodes)
+        }
+    }
+}
+
+/// `build` with the two halves of every subtree larger than
+/// `PARALLEL_SUBTREE_SIZE` built on the rayon pool.
+fn build_parallel(depth: usize, entries: &[Entry]) -> ([u8; 32], Vec<([u8; 32], StateNode)>) {
+    let mut nodes = Vec::new();
+    if entries.len() <= PARALLEL_SUBTREE_SIZE {
+        let root = build(&mut Hasher::new(), depth, entries, &mut nodes);
+        return (root, nodes);
+    }
+    let split = entries.partition_point(|entry| !bit(&entry.path, depth));
+    let ((left, left_nodes), (right, right_nodes)) = rayon::join(
+        || build_parallel(depth + 1, &entries[..split]),
+        || build_parallel(depth + 1, &entries[split..]),
+    );
+    nodes.extend(left_nodes);
+    nodes.extend(right_nodes);
+    let root = internal(&mut Hasher::new(), left, right, &mut nodes);
+    (root, nodes)
+}
+
+fn internal(hasher: &mut Hasher, left: [u8; 32], right: [u8; 32], nodes: &mut Vec<([u8; 32], StateNode)>) -> [u8; 32] {
+    let hash = hasher.tree_node(left, right);
+    nodes.push((hash, StateNode::Internal { left, right }));
+    hash
+}
+
+/// Proof that a state root holds a given account, or no account, under a key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// Sibling hashes along the key's path, from the root down.
+    pub siblings: Vec<[u8; 32]>,
+    /// For an absent key whose path ends at another account's leaf rather
+    /// than an empty subtree: that account.
+    pub other_leaf: Option<(AccountKey, Account)>,
+}
+
+impl MerkleProof {
+    /// Checks that `root` holds `account` under `key`, or no account at all
+    /// when `account` is `None`. Needs no storage, so clients can check
+    /// proofs against a header they trust.
+    pub fn verify(&self, root: &[u8; 32], key: &AccountKey, account: Option<&Account>) -> bool {
+        if self.siblings.len() > TREE_DEPTH { return false; }
+        let mut hasher = Hasher::new();
+        let path = hasher.key_path(key);
+        let mut hash = match (account, &self.other_leaf) {
+            (Some(account), None) => hasher.tree_leaf(key, account),
+            (None, None) => EMPTY_ROOT,
+            (None, Some((other_key, other_account))) => {
+                // The other leaf must end this key's path, so share its prefix.
+                let other_path = hasher.key_path(other_key);
+                if other_path == path || (0..self.siblings.len()).any(|depth| bit(&other_path, depth) != bit(&path, depth)) {
+                    return false;
+                }
+                hasher.tree_leaf(other_key, other_account)
+            }
+            (Some(_), Some(_)) => return false,
+        };
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            hash = if bit(&path, depth) { hasher.tree_node(*sibling, hash) } else { hasher.tree_node(hash, *sibling) };
+        }
+        hash == *root
+    }
+}
+
+/// An account opened against a committed batch: everything a client needs
+/// to check a balance without trusting the node that served it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountProof {
+    pub header: BlockHeader,
+    pub key: AccountKey,
+    /// `None` when the proof shows `key` has no account at this batch.
+    pub account: Option<Account>,
+    pub proof: MerkleProof,
+}
+
+impl AccountProof {
+    /// Checks the proof against `header.new_root`, which the header's flags
+    /// must say is a state tree root. The header itself must still be
+    /// matched against one the client trusts, such as the root settled on L1.
+    pub fn verify(&self) -> bool {
+        CommitmentScheme::from_flags(self.header.flags) == Some(CommitmentScheme::SparseMerkle)
+            && self.proof.verify(&self.header.new_root, &self.key, self.account.as_ref())
+    }
+}
+
+/// Bit `depth` of `path`, most significant first.
+fn bit(path: &[u8; 32], depth: usize) -> bool {
+    path[depth / 8] >> (7 - depth % 8) & 1 == 1
+}
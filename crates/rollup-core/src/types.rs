@@ -1,48 +1,311 @@
 use serde::{Serialize,Deserialize};
-use byteorder::{BigEndian,ByteOrder,ReadBytesExt,WriteBytesExt};
+use byteorder::{BigEndian,ReadBytesExt,WriteBytesExt};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
 use std::io::{Cursor,Read,Write};
 
-#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug,PartialOrd,Ord,Serialize,Deserialize)]
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug,Default,PartialOrd,Ord,Serialize,Deserialize)]
 pub struct Pubkey(pub [u8;32]);
 
 impl Pubkey{
     pub fn new(bytes:[u8;32])->Self{Self(bytes)}
 }
 
+/// Mint of the native zSOL asset. Fees and nonces live on native accounts.
+pub const NATIVE_MINT: Pubkey = Pubkey([0; 32]);
+
+/// Sender of deposits bridged from L1. Its transactions carry no signature,
+/// fee or meaningful nonce.
+pub const SYSTEM_SENDER: Pubkey = Pubkey([0; 32]);
+
+/// Identifies one token balance: the `owner`'s holding of `mint`.
+/// Stored in the `accounts` column family as `owner || mint`.
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug,PartialOrd,Ord,Serialize,Deserialize)]
+pub struct AccountKey{
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+}
+
+impl AccountKey{
+    pub fn new(owner: Pubkey, mint: Pubkey)->Self{Self{owner, mint}}
+
+    /// The owner's native zSOL account.
+    pub fn native(owner: Pubkey)->Self{Self{owner, mint: NATIVE_MINT}}
+
+    pub fn to_bytes(&self)->[u8;64]{
+        let mut bytes = [0u8;64];
+        bytes[..32].copy_from_slice(&self.owner.0);
+        bytes[32..].copy_from_slice(&self.mint.0);
+        bytes
+    }
+
+    pub fn from_bytes(bytes:&[u8])->Option<Self>{
+        let bytes: &[u8;64] = bytes.try_into().ok()?;
+        Some(Self{
+            owner: Pubkey(bytes[..32].try_into().unwrap()),
+            mint: Pubkey(bytes[32..].try_into().unwrap()),
+        })
+    }
+}
+
 /// The state of an account.
-#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[derive(Clone,Debug,Default,PartialEq,Serialize,Deserialize)]
 pub struct Account{
     pub balance:u64,
     pub nonce: u64
 }
 
+/// An ed25519 signature over `Transaction::signing_message`.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Signature(
-    #[serde(with = "serde_bytes")] pub [u8;32]
+    #[serde(with = "serde_bytes")] pub [u8;64]
 );
 
+/// Identifies one deployment of the rollup. Part of every signing message, so
+/// a transaction signed for one chain is invalid on every other. Must equal
+/// the `domain` in the L1 bridge config.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct ChainId(pub [u8; 32]);
+
+impl ChainId {
+    /// Development chain, matching the domain `bon`'s `init_config` sets.
+    pub const DEVNET: ChainId = ChainId([1; 32]);
+
+    pub fn from_hex(s: &str) -> Option<Self> {
+        Some(Self(hex::decode(s).ok()?.try_into().ok()?))
+    }
+}
+
+/// Content-addressed transaction id, see `Transaction::hash`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TxHash(pub [u8;32]);
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum TransactionType {
-    Transfer { amount: u64 },
-    Deposit { amount: u64 },
+    Transfer { amount: u64, #[serde(default)] mint: Pubkey },
+    Deposit { amount: u64, #[serde(default)] mint: Pubkey },
+    /// Burns `amount` from the sender and queues it for release to `l1_recipient` on L1.
+    Withdraw { amount: u64, l1_recipient: Pubkey, #[serde(default)] mint: Pubkey },
+    /// Runs `instructions` in order, all or nothing. `Transaction::recipient`
+    /// is unused; each instruction names its own.
+    Multi { instructions: Vec<Instruction> },
+}
+
+/// Most instructions a `TransactionType::Multi` may carry.
+pub const MAX_INSTRUCTIONS: usize = 64;
+
+/// One step of a `TransactionType::Multi`, always acting on the sender's balances.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Instruction {
+    Transfer { recipient: Pubkey, amount: u64, #[serde(default)] mint: Pubkey },
+    Withdraw { amount: u64, l1_recipient: Pubkey, #[serde(default)] mint: Pubkey },
 }
 
 /// A single transaction 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Transaction {
+    /// Chain the sender signed for. Executed only on the matching chain.
+    pub chain_id: ChainId,
     pub sender: Pubkey,
     pub recipient: Pubkey,
     pub tx_type: TransactionType,
+    /// Must equal the sender's `Account::nonce` when executed.
+    pub nonce: u64,
+    /// Charged to the sender before execution, even if execution then fails.
+    pub fee: u64,
+    /// Last batch id that may include this transaction. Later batches reject
+    /// it as expired. `None` never expires.
+    #[serde(default)]
+    pub valid_until_batch: Option<u64>,
     pub signature: Signature,
 }
 
+/// Domain prefix of every transaction signing message.
+pub const TX_SIGNING_DOMAIN: &[u8] = b"zelana:tx:v1";
+
+impl Transaction {
+    /// The canonical byte encoding the sender signs.
+    ///
+    /// Layout: `TX_SIGNING_DOMAIN || chain_id || sender || recipient || nonce || fee ||
+    /// valid_until_batch || tx_type`, with no expiry encoded as `u64::MAX`,
+    /// where `tx_type` is a one-byte tag followed by its big-endian fields. A
+    /// `Multi` body is a `u32` instruction count followed by each instruction,
+    /// itself tagged the same way.
+    pub fn signing_message(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(TX_SIGNING_DOMAIN.len() + 32 + 32 + 32 + 8 + 8 + 8 + 1 + 8 + 32 + 32);
+        msg.extend_from_slice(TX_SIGNING_DOMAIN);
+        msg.extend_from_slice(&self.chain_id.0);
+        msg.extend_from_slice(&self.sender.0);
+        msg.extend_from_slice(&self.recipient.0);
+        msg.extend_from_slice(&self.nonce.to_be_bytes());
+        msg.extend_from_slice(&self.fee.to_be_bytes());
+        msg.extend_from_slice(&self.valid_until_batch.unwrap_or(u64::MAX).to_be_bytes());
+        match self.tx_type {
+            TransactionType::Transfer { amount, mint } => {
+                msg.push(0);
+                msg.extend_from_slice(&amount.to_be_bytes());
+                msg.extend_from_slice(&mint.0);
+            }
+            TransactionType::Deposit { amount, mint } => {
+                msg.push(1);
+                msg.extend_from_slice(&amount.to_be_bytes());
+                msg.extend_from_slice(&mint.0);
+            }
+            TransactionType::Withdraw { amount, l1_recipient, mint } => {
+                msg.push(2);
+                msg.extend_from_slice(&amount.to_be_bytes());
+                msg.extend_from_slice(&l1_recipient.0);
+                msg.extend_from_slice(&mint.0);
+            }
+            TransactionType::Multi { ref instructions } => {
+                msg.push(3);
+                msg.extend_from_slice(&(instructions.len() as u32).to_be_bytes());
+                for instruction in instructions {
+                    match *instruction {
+                        Instruction::Transfer { recipient, amount, mint } => {
+                            msg.push(0);
+                            msg.extend_from_slice(&recipient.0);
+                            msg.extend_from_slice(&amount.to_be_bytes());
+                            msg.extend_from_slice(&mint.0);
+                        }
+                        Instruction::Withdraw { amount, l1_recipient, mint } => {
+                            msg.push(1);
+                            msg.extend_from_slice(&amount.to_be_bytes());
+                            msg.extend_from_slice(&l1_recipient.0);
+                            msg.extend_from_slice(&mint.0);
+                        }
+                    }
+                }
+            }
+        }
+        msg
+    }
+
+    /// SHA-256 of the signing message. The signature is excluded, so the id
+    /// cannot be changed without changing what was signed.
+    pub fn hash(&self) -> TxHash {
+        TxHash(Sha256::digest(self.signing_message()).into())
+    }
+
+    /// Work the sequencer budgets for this transaction: one unit per
+    /// instruction, counting a single transfer, deposit or withdrawal as one.
+    pub fn compute_units(&self) -> u64 {
+        match &self.tx_type {
+            TransactionType::Multi { instructions } => instructions.len() as u64,
+            _ => 1,
+        }
+    }
+
+    /// Signs the transaction in place. `key` must belong to `sender`.
+    pub fn sign(&mut self, key: &SigningKey) {
+        self.signature = Signature(key.sign(&self.signing_message()).to_bytes());
+    }
+
+    /// Checks `signature` against `sender` as an ed25519 public key.
+    pub fn verify_signature(&self) -> bool {
+        let Ok(key) = VerifyingKey::from_bytes(&self.sender.0) else { return false };
+        let signature = ed25519_dalek::Signature::from_bytes(&self.signature.0);
+        key.verify_strict(&self.signing_message(), &signature).is_ok()
+    }
+}
+
+/// An exit burned on L2 and waiting to be settled on L1 by the bridge.
+/// Stored in the `withdrawals` column family under `batch_id || index`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub tx_hash: TxHash,
+    pub sender: Pubkey,
+    pub l1_recipient: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Outcome of a transaction the sequencer processed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptStatus {
+    /// Fee charged and body applied.
+    Success,
+    /// Fee charged and nonce consumed, but the body was rolled back.
+    Failed,
+    /// Failed validation; nothing was charged and the nonce is still free.
+    Rejected,
+}
+
+/// One balance a transaction changed, before and after it ran.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BalanceChange {
+    pub key: AccountKey,
+    pub before: u64,
+    pub after: u64,
+}
+
+/// Execution record of a transaction. Stored in the `receipts` column family
+/// under the transaction hash.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub tx_hash: TxHash,
+    pub batch_id: u64,
+    /// Position of the transaction within its batch.
+    pub index: u32,
+    pub status: ReceiptStatus,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+/// Domain separator prefixed to every hashed receipt.
+pub const RECEIPT_HASH_DOMAIN: &[u8] = b"zelana:receipt:v1";
+
+impl Receipt {
+    /// SHA-256 of the receipt's canonical encoding: domain, tx hash, batch ID
+    /// BE, index BE, a status byte (0 success, 1 failed, 2 rejected), the
+    /// error code and message each as 0 when absent or 1 followed by a BE u32
+    /// length and UTF-8 bytes, then a BE u32 count of balance changes, each
+    /// owner, mint, before BE and after BE. Leaves of `BlockHeader::receipts_root`.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(RECEIPT_HASH_DOMAIN);
+        hasher.update(self.tx_hash.0);
+        hasher.update(self.batch_id.to_be_bytes());
+        hasher.update(self.index.to_be_bytes());
+        hasher.update([match self.status {
+            ReceiptStatus::Success => 0,
+            ReceiptStatus::Failed => 1,
+            ReceiptStatus::Rejected => 2,
+        }]);
+        for text in [&self.error_code, &self.error_message] {
+            match text {
+                Some(text) => {
+                    hasher.update([1]);
+                    hasher.update((text.len() as u32).to_be_bytes());
+                    hasher.update(text.as_bytes());
+                }
+                None => hasher.update([0]),
+            }
+        }
+        hasher.update((self.balance_changes.len() as u32).to_be_bytes());
+        for change in &self.balance_changes {
+            hasher.update(change.key.owner.0);
+            hasher.update(change.key.mint.0);
+            hasher.update(change.before.to_be_bytes());
+            hasher.update(change.after.to_be_bytes());
+        }
+        hasher.finalize().into()
+    }
+}
+
 // Block header
 pub const HEADER_MAGIC: [u8; 4] = *b"ZLNA";
-pub const HEADER_VERSION: u16 = 1;
-pub const HEADER_SIZE: usize = 96;
+pub const HEADER_VERSION: u16 = 2;
+pub const HEADER_SIZE: usize = 160;
+/// Size of version 1 headers, which end after `flags`.
+pub const HEADER_SIZE_V1: usize = 96;
+/// Bits of `BlockHeader::flags` holding the state commitment scheme id
+/// (`sequencer::CommitmentScheme`).
+pub const FLAGS_COMMITMENT_MASK: u32 = 0xff;
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BlockHeader {
     #[serde(with = "hex::serde")]
     pub magic: [u8; 4],
@@ -50,16 +313,33 @@ pub struct BlockHeader {
     pub batch_id: u64,
     #[serde(with = "hex::serde")]
     pub prev_root: [u8; 32],
+    /// State commitment after this batch, from the scheme named in `flags`.
     #[serde(with = "hex::serde")]
     pub new_root: [u8; 32],
+    /// Transactions included in the batch, i.e. charged a fee. Rejected
+    /// transactions get receipts but are not counted.
     pub tx_count: u32,
     pub open_at: u64,
+    /// Low byte: the scheme that produced `new_root`, see
+    /// `FLAGS_COMMITMENT_MASK`. Other bits are reserved and zero.
     pub flags: u32,
+    /// Merkle root (`sequencer::merkle_root`) of the hashes of every
+    /// transaction in the batch, rejected ones included, in execution order.
+    /// Zero in version 1 headers.
+    #[serde(with = "hex::serde")]
+    pub tx_root: [u8; 32],
+    /// Merkle root of `Receipt::hash` over the batch's receipts, in index
+    /// order. Zero in version 1 headers.
+    #[serde(with = "hex::serde")]
+    pub receipts_root: [u8; 32],
 }
 
 impl BlockHeader{
-    pub fn to_bytes(&self)->Result<[u8;HEADER_SIZE],std::io::Error>{
-        let mut bytes = [0u8;HEADER_SIZE];
+    /// Encodes the header in the layout of its `hdr_version`. Version 2 is
+    /// version 1 with `tx_root` and `receipts_root` appended.
+    pub fn to_bytes(&self)->Result<Vec<u8>,std::io::Error>{
+        let size = header_size(self.hdr_version)?;
+        let mut bytes = vec![0u8; size];
         let mut cursor = Cursor::new(&mut bytes[..]);
         cursor.write_all(&self.magic)?;
         cursor.write_u16::<BigEndian>(self.hdr_version)?;
@@ -70,16 +350,29 @@ impl BlockHeader{
         cursor.write_u32::<BigEndian>(self.tx_count)?;
         cursor.write_u64::<BigEndian>(self.open_at)?;
         cursor.write_u32::<BigEndian>(self.flags)?;
+        if self.hdr_version >= 2 {
+            cursor.write_all(&self.tx_root)?;
+            cursor.write_all(&self.receipts_root)?;
+        }
 
         Ok(bytes)
     }
 
-    pub fn from_bytes(bytes:&[u8;HEADER_SIZE])->Result<Self,std::io::Error>{
-        let mut cursor = Cursor::new(&bytes[..]);
+    /// Decodes a header of any supported version. Fields a version lacks are
+    /// zero.
+    pub fn from_bytes(bytes:&[u8])->Result<Self,std::io::Error>{
+        let mut cursor = Cursor::new(bytes);
         let mut magic = [0u8;4];
         cursor.read_exact(&mut magic)?;
         
         let hdr_version = cursor.read_u16::<BigEndian>()?;
+        let size = header_size(hdr_version)?;
+        if bytes.len() != size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Version {} header must be {} bytes, got {}", hdr_version, size, bytes.len()),
+            ));
+        }
         cursor.read_u16::<BigEndian>()?;
 
         let batch_id = cursor.read_u64::<BigEndian>()?;
@@ -90,7 +383,13 @@ impl BlockHeader{
         let tx_count = cursor.read_u32::<BigEndian>()?;
         let open_at = cursor.read_u64::<BigEndian>()?;
         let flags = cursor.read_u32::<BigEndian>()?;
-        Ok(Self { magic, hdr_version, batch_id, prev_root, new_root, tx_count, open_at, flags })
+        let mut tx_root = [0u8; 32];
+        let mut receipts_root = [0u8; 32];
+        if hdr_version >= 2 {
+            cursor.read_exact(&mut tx_root)?;
+            cursor.read_exact(&mut receipts_root)?;
+        }
+        Ok(Self { magic, hdr_version, batch_id, prev_root, new_root, tx_count, open_at, flags, tx_root, receipts_root })
     }
     pub fn genesis() -> Self {
         Self {
@@ -102,6 +401,16 @@ impl BlockHeader{
             tx_count: 0,
             open_at: 0,
             flags: 0,
+            tx_root: [0; 32],
+            receipts_root: [0; 32],
         }
     }
+}
+
+fn header_size(hdr_version: u16) -> Result<usize, std::io::Error> {
+    match hdr_version {
+        1 => Ok(HEADER_SIZE_V1),
+        2 => Ok(HEADER_SIZE),
+        _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Unsupported header version {}", hdr_version))),
+    }
 }
\ No newline at end of file
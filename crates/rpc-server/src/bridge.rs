@@ -0,0 +1,77 @@
+use base64::Engine;
+use serde_json::{json, Value};
+
+/// The bridge program, as deployed for `bon`'s clients.
+pub const BRIDGE_PROGRAM_ID: &str = "95sWqtU9fdm19cvQYu94iKijRuYAv3wLqod1pcsSfYth";
+
+/// The bridge program's config account, as created by its `Initialize`
+/// instruction from `InitParams { sequencer_authority, domain }`.
+///
+/// The program's source is not in this repository, so this layout is
+/// inferred, not taken from its account struct: `bon`'s `init_config` sends
+/// the params in this order and expects the account to store them the same
+/// way, with no discriminator or bump. Parsing therefore fails closed: an
+/// account not owned by `BRIDGE_PROGRAM_ID`, or of any size but `LEN`, is
+/// refused rather than sliced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BridgeConfig {
+    pub sequencer_authority: [u8; 32],
+    /// Domain separator for L1 messages; must equal the rollup's chain ID.
+    pub domain: [u8; 32],
+}
+
+impl BridgeConfig {
+    /// Size of the config account.
+    pub const LEN: usize = 32 + 32;
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, String> {
+        if data.len() != Self::LEN {
+            return Err(format!("Bridge config account has {} bytes, expected {}", data.len(), Self::LEN));
+        }
+        Ok(Self {
+            sequencer_authority: data[..32].try_into().expect("length checked"),
+            domain: data[32..64].try_into().expect("length checked"),
+        })
+    }
+
+    /// Decodes the config from a `getAccountInfo` response with base64 encoding.
+    pub fn from_account_info(response: &Value) -> Result<Self, String> {
+        if let Some(error) = response.get("error") {
+            return Err(format!("getAccountInfo failed: {}", error));
+        }
+        let account = &response["result"]["value"];
+        let data = account["data"][0].as_str().ok_or("Bridge config account not found")?;
+        if account["owner"] != BRIDGE_PROGRAM_ID {
+            return Err(format!("Bridge config account is owned by {}, not the bridge program {}", account["owner"], BRIDGE_PROGRAM_ID));
+        }
+        let data = base64::engine::general_purpose::STANDARD.decode(data).map_err(|e| format!("Invalid account data: {}", e))?;
+        Self::from_account_data(&data)
+    }
+}
+
+/// Fetches the bridge config account from L1.
+pub async fn fetch_bridge_config(rpc_url: &str, config_pubkey: &str) -> Result<BridgeConfig, Box<dyn std::error::Error>> {
+    let response: Value = reqwest::Client::new()
+        .post(rpc_url)
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "getAccountInfo",
+            "params": [config_pubkey, { "encoding": "base64" }],
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(BridgeConfig::from_account_info(&response)?)
+}
+
+/// Fails unless the bridge config's domain is `chain_id`.
+pub async fn check_chain_id(rpc_url: &str, config_pubkey: &str, chain_id: &[u8; 32]) -> Result<(), Box<dyn std::error::Error>> {
+    let config = fetch_bridge_config(rpc_url, config_pubkey).await?;
+    if config.domain != *chain_id {
+        return Err(format!("CHAIN_ID {} does not match bridge domain {}", hex::encode(chain_id), hex::encode(config.domain)).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::{error::ApiError, state::AppState};
+use rollup_core::sequencer::ExecutionError;
+use rollup_core::types::{Account, AccountKey, Pubkey, Signature, Transaction, TransactionType, TxHash, SYSTEM_SENDER};
+
+/// Queues a deposit locked in the L1 bridge, to be credited to `recipient`
+/// when the sequencer includes it. Deposits come from `SYSTEM_SENDER` and are
+/// unsigned, so only the node itself may call this; `send_transaction`
+/// refuses the system sender.
+///
+/// `nonce` identifies the deposit on L1. Submitting the same recipient,
+/// amount, mint and nonce again is the same transaction, so it is credited at
+/// most once. A deposit that would overflow the recipient's committed balance
+/// is refused up front.
+pub async fn submit_deposit(state: &AppState, recipient: Pubkey, amount: u64, mint: Pubkey, nonce: u64) -> Result<TxHash, ApiError> {
+    if let Some(bytes) = state.storage.rocksdb.get_cf(state.storage.cf_accounts(), AccountKey::new(recipient, mint).to_bytes())? {
+        let account: Account = bincode::deserialize(&bytes)?;
+        account.balance.checked_add(amount).ok_or(ExecutionError::Overflow)?;
+    }
+
+    let deposit = Transaction {
+        chain_id: state.chain_id,
+        sender: SYSTEM_SENDER,
+        recipient,
+        tx_type: TransactionType::Deposit { amount, mint },
+        nonce,
+        fee: 0,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    let tx_hash = deposit.hash();
+    state.enqueue(deposit).await?;
+    Ok(tx_hash)
+}
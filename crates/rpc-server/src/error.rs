@@ -2,6 +2,7 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Json},
 };
+use rollup_core::{db::DbError, sequencer::{ExecutionError, MempoolError}};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -15,10 +16,74 @@ pub struct ErrorBody {
     message: String,
 }
 
+#[derive(Debug)]
 pub enum ApiError {
     NotFound(String),
     BadRequest(String),
-    DatabaseUnavailable(String),
+    InvalidSignature(String),
+    NonceTooLow(String),
+    NonceTooHigh(String),
+    Execution(ExecutionError),
+    /// The mempool turned the transaction away.
+    Mempool(MempoolError),
+    Storage(DbError),
+    SequencerUnavailable(String),
+}
+
+impl From<ExecutionError> for ApiError {
+    fn from(e: ExecutionError) -> Self {
+        match e {
+            ExecutionError::Storage(e) => ApiError::Storage(e),
+            e => ApiError::Execution(e),
+        }
+    }
+}
+
+impl From<MempoolError> for ApiError {
+    fn from(e: MempoolError) -> Self { ApiError::Mempool(e) }
+}
+
+impl From<DbError> for ApiError {
+    fn from(e: DbError) -> Self { ApiError::Storage(e) }
+}
+
+impl From<rocksdb::Error> for ApiError {
+    fn from(e: rocksdb::Error) -> Self { ApiError::Storage(e.into()) }
+}
+
+impl From<bincode::Error> for ApiError {
+    fn from(e: bincode::Error) -> Self { ApiError::Storage(e.into()) }
+}
+
+fn execution_status(e: &ExecutionError) -> StatusCode {
+    match e {
+        ExecutionError::InvalidSignature => StatusCode::UNAUTHORIZED,
+        ExecutionError::BadNonce { .. } => StatusCode::CONFLICT,
+        ExecutionError::SenderNotFound => StatusCode::NOT_FOUND,
+        ExecutionError::UnauthorizedDeposit => StatusCode::BAD_REQUEST,
+        ExecutionError::WrongChain
+        | ExecutionError::FeeTooLow { .. }
+        | ExecutionError::Expired { .. }
+        | ExecutionError::InvalidInstructionCount { .. }
+        | ExecutionError::InsufficientFunds
+        | ExecutionError::Overflow => StatusCode::UNPROCESSABLE_ENTITY,
+        ExecutionError::Storage(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+fn mempool_status(e: &MempoolError) -> StatusCode {
+    match e {
+        MempoolError::Duplicate | MempoolError::Underpriced => StatusCode::CONFLICT,
+        MempoolError::SenderFull => StatusCode::TOO_MANY_REQUESTS,
+        MempoolError::Full => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+fn storage_error(e: &DbError) -> (StatusCode, &'static str) {
+    match e {
+        DbError::RocksDb(_) | DbError::Sqlite(_) | DbError::Io(_) => (StatusCode::SERVICE_UNAVAILABLE, "db_unavailable"),
+        DbError::Codec(_) | DbError::Corrupt(_) => (StatusCode::INTERNAL_SERVER_ERROR, "db_corrupt"),
+    }
 }
 
 impl IntoResponse for ApiError {
@@ -26,8 +91,17 @@ impl IntoResponse for ApiError {
         let (status, error_code, error_message) = match self {
             ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, "not_found", msg),
             ApiError::BadRequest(msg) => (StatusCode::BAD_REQUEST, "bad_request", msg),
-            ApiError::DatabaseUnavailable(msg) => {
-                (StatusCode::SERVICE_UNAVAILABLE, "db_unavailable", msg)
+            ApiError::InvalidSignature(msg) => (StatusCode::UNAUTHORIZED, "invalid_signature", msg),
+            ApiError::NonceTooLow(msg) => (StatusCode::CONFLICT, "nonce_too_low", msg),
+            ApiError::NonceTooHigh(msg) => (StatusCode::CONFLICT, "nonce_too_high", msg),
+            ApiError::Execution(e) => (execution_status(&e), e.code(), e.to_string()),
+            ApiError::Mempool(e) => (mempool_status(&e), e.code(), e.to_string()),
+            ApiError::Storage(e) => {
+                let (status, code) = storage_error(&e);
+                (status, code, e.to_string())
+            }
+            ApiError::SequencerUnavailable(msg) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "sequencer_unavailable", msg)
             }
         };
 
@@ -38,4 +112,3 @@ impl IntoResponse for ApiError {
         (status, [("Content-Type", "application/json")], body).into_response()
     }
 }
-
@@ -1,3 +1,5 @@
+pub mod bridge;
+pub mod deposits;
 pub mod error;
 pub mod state;
 pub mod routes;
\ No newline at end of file
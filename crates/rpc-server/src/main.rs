@@ -1,16 +1,24 @@
+mod bridge;
 mod error;
 mod routes;
 mod state;
 
+use ed25519_dalek::SigningKey;
 use rollup_core::{
     db::{reconcile_databases_on_startup,Storage},
-    sequencer::RollupCore,
-    types::{Account, Pubkey, Signature, Transaction, TransactionType},
+    sequencer::{CommitmentScheme, FeeConfig, RollupCore, SequencerConfig},
+    types::{Account, AccountKey, ChainId, Pubkey, Signature, Transaction, TransactionType, NATIVE_MINT},
 };
 use state::AppState;
-use std::{env, path::PathBuf, sync::Arc};
-use tempfile::tempdir;
-use tokio::sync::mpsc;
+use std::{env, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::{mpsc, oneshot};
+
+/// Well-known development key that owns the genesis account.
+const GENESIS_KEY_SEED: [u8; 32] = [1; 32];
+
+/// How long shutdown may take to seal the final batch before the node exits
+/// anyway, unless SHUTDOWN_TIMEOUT_MS is set.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 
 #[tokio::main]
@@ -30,31 +38,116 @@ async fn main()->Result<(),Box<dyn std::error::Error>>{
     println!("[Main] Storage initialized and reconciled.");
 
 
+    // Fees are credited to FEE_COLLECTOR (hex pubkey, required) and must be
+    // at least MIN_FEE.
+    let collector_hex = env::var("FEE_COLLECTOR").map_err(|_| "FEE_COLLECTOR must be set to the hex pubkey that receives fees")?;
+    let collector: [u8; 32] = hex::decode(&collector_hex)?
+        .try_into()
+        .map_err(|_| "FEE_COLLECTOR must be a 32-byte hex pubkey")?;
+    let mut fees = FeeConfig { collector: Pubkey::new(collector), min_fee: 0 };
+    if let Ok(min_fee) = env::var("MIN_FEE") {
+        fees.min_fee = min_fee.parse()?;
+    }
+    println!("[Main] Fees go to {} (min fee {}).", hex::encode(fees.collector.0), fees.min_fee);
+
+    // Transactions must be signed for CHAIN_ID (hex), which must also be the
+    // L1 bridge's domain, read from the BRIDGE_CONFIG account (base58) over
+    // L1_RPC_URL. The node refuses to start without that check unless
+    // SKIP_BRIDGE_CHECK=1, for local development only.
+    let chain_id = match env::var("CHAIN_ID") {
+        Ok(chain_id_hex) => ChainId::from_hex(&chain_id_hex).ok_or("CHAIN_ID must be a 32-byte hex string")?,
+        Err(_) => ChainId::DEVNET,
+    };
+    println!("[Main] Chain ID is {}.", hex::encode(chain_id.0));
+    match (env::var("L1_RPC_URL"), env::var("BRIDGE_CONFIG")) {
+        (Ok(rpc_url), Ok(bridge_config)) => {
+            bridge::check_chain_id(&rpc_url, &bridge_config, &chain_id.0).await?;
+            println!("[Main] Chain ID matches the bridge config domain.");
+        }
+        _ if env::var("SKIP_BRIDGE_CHECK").is_ok_and(|skip| skip == "1") => {
+            println!("[Main] SKIP_BRIDGE_CHECK=1: chain ID is NOT checked against the bridge. Development only.");
+        }
+        _ => return Err("L1_RPC_URL and BRIDGE_CONFIG must be set to check CHAIN_ID against the bridge (or SKIP_BRIDGE_CHECK=1 for development)".into()),
+    }
+
+    // STATE_COMMITMENT picks how batches commit to the accounts: "smt"
+    // (default) or "fold". Each header records the scheme it was sealed with,
+    // and the node refuses to start with a different one than its tip's.
+    let commitment = match env::var("STATE_COMMITMENT") {
+        Ok(scheme) => scheme.parse::<CommitmentScheme>()?,
+        Err(_) => CommitmentScheme::default(),
+    };
+    println!("[Main] State commitment scheme is {}.", commitment);
+
+    // Batch limits, read from the environment or .env like the rest of the
+    // node config. All optional (see `SequencerConfig` for defaults): a
+    // batch is sealed once its transactions reach MAX_TX_PER_BATCH,
+    // MAX_BATCH_BYTES or MAX_BATCH_COMPUTE, or SEAL_TIMEOUT_MS after the
+    // oldest arrived. The mempool holds at most MAX_PENDING_TXS, and
+    // MAX_PENDING_PER_SENDER from any one sender. Each must be above zero.
+    let mut sequencer_config = SequencerConfig::default();
+    if let Ok(max_txs) = env::var("MAX_TX_PER_BATCH") {
+        sequencer_config.max_txs_per_batch = max_txs.parse()?;
+    }
+    if let Ok(max_bytes) = env::var("MAX_BATCH_BYTES") {
+        sequencer_config.max_batch_bytes = max_bytes.parse()?;
+    }
+    if let Ok(max_compute) = env::var("MAX_BATCH_COMPUTE") {
+        sequencer_config.max_batch_compute = max_compute.parse()?;
+    }
+    if let Ok(millis) = env::var("SEAL_TIMEOUT_MS") {
+        sequencer_config.seal_timeout = Duration::from_millis(millis.parse()?);
+    }
+    if let Ok(max_pending) = env::var("MAX_PENDING_TXS") {
+        sequencer_config.max_pending_txs = max_pending.parse()?;
+    }
+    if let Ok(max_pending) = env::var("MAX_PENDING_PER_SENDER") {
+        sequencer_config.max_pending_per_sender = max_pending.parse()?;
+    }
+    sequencer_config.validate()?;
+    println!("[Main] Sequencer config: {:?}.", sequencer_config);
+
+    // On SIGINT or SIGTERM the node stops taking transactions, seals what is
+    // pending and flushes both databases. After SHUTDOWN_TIMEOUT_MS it gives
+    // up and exits with an error, without flushing; transactions still
+    // pending then are restored on the next start.
+    let shutdown_timeout = match env::var("SHUTDOWN_TIMEOUT_MS") {
+        Ok(millis) => Duration::from_millis(millis.parse()?),
+        Err(_) => DEFAULT_SHUTDOWN_TIMEOUT,
+    };
+
     // intiialize and run rollupcore 
     let (tx_sender, tx_receiver) = mpsc::channel(100);
     let core_storage = Arc::clone(&storage);
-    let rollup_core = RollupCore::new(core_storage, tx_receiver).await?;
-    let core_handle = tokio::spawn(rollup_core.run());
+    let rollup_core = RollupCore::new(core_storage, tx_receiver, sequencer_config, fees, chain_id).await?
+        .with_commitment(commitment.build())?;
+    let mut core_handle = tokio::spawn(rollup_core.run());
     println!("[Main] RollupCore service started in the background.");
 
     // This is a placeholder for genesis account creation.
     // In a real app, this would be handled by a genesis file loader.
+    let genesis_key = SigningKey::from_bytes(&GENESIS_KEY_SEED);
+    let genesis_pubkey = Pubkey::new(genesis_key.verifying_key().to_bytes());
     if storage.rocksdb.iterator_cf(storage.cf_batches(), rocksdb::IteratorMode::Start).next().is_none() {
         let mut initial_batch = rocksdb::WriteBatch::default();
         let cf = storage.cf_accounts();
-        initial_batch.put_cf(cf, bincode::serialize(&Pubkey::new([1;32]))?, bincode::serialize(&Account{balance: 1_000_000, nonce: 0})?);
+        initial_batch.put_cf(cf, AccountKey::native(genesis_pubkey).to_bytes(), bincode::serialize(&Account{balance: 1_000_000, nonce: 0})?);
         storage.rocksdb.write(initial_batch)?;
         println!("[Main] Genesis accounts populated.");
     }
 
     // Start the RPC Server 
-    let rpc_state = AppState { storage , tx_sender:tx_sender.clone()};
+    let rpc_state = AppState { storage: Arc::clone(&storage), tx_sender:tx_sender.clone(), chain_id, sequencer_config };
     let app = routes::create_router(rpc_state);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
     println!("[RPC] Server listening on 0.0.0.0:3000");
-    let rpc_handle = tokio::spawn(async move {
+    let (stop_rpc, rpc_stopped) = oneshot::channel::<()>();
+    let mut rpc_handle = tokio::spawn(async move {
         println!("[RPC] Server started.");
-        if let Err(err) = axum::serve(listener, app).await {
+        // Stops accepting connections and finishes in-flight requests, then
+        // drops the router and its senders to the core.
+        let shutdown = async { let _ = rpc_stopped.await; };
+        if let Err(err) = axum::serve(listener, app).with_graceful_shutdown(shutdown).await {
             eprintln!("[RPC] Server error: {}", err);
         }
     });
@@ -63,29 +156,97 @@ async fn main()->Result<(),Box<dyn std::error::Error>>{
     // In a real system, these would come from the RPC write endpoints or the L1 listener.
     tokio::time::sleep(tokio::time::Duration::from_secs(1)).await; // Give server a moment to start
     println!("[Main] Simulating incoming transactions...");
-    tx_sender.send(Transaction {
-        sender: Pubkey::new([1; 32]),
+    let mut demo_tx = Transaction {
+        chain_id,
+        sender: genesis_pubkey,
+        recipient: Pubkey::new([2; 32]),
+        tx_type: TransactionType::Transfer { amount: 100, mint: NATIVE_MINT },
+        nonce: 0,
+        fee: fees.min_fee,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    demo_tx.sign(&genesis_key);
+    tx_sender.send(demo_tx.into()).await?;
+
+    let mut example_tx = Transaction {
+        chain_id,
+        sender: genesis_pubkey,
         recipient: Pubkey::new([2; 32]),
-        tx_type: TransactionType::Transfer { amount: 100 },
-        signature: Signature([0; 32]),
-    }).await?;
-    
+        tx_type: TransactionType::Transfer { amount: 10, mint: NATIVE_MINT },
+        nonce: 1,
+        fee: fees.min_fee,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    example_tx.sign(&genesis_key);
+
     println!("\nServer is running. Try the following commands:");
     println!("\nAll services are running. You can now send transactions to the RPC server.");
     println!("Example using curl:");
     println!(r#"  curl -X POST http://127.0.0.1:3000/v1/send_transaction \
   -H "Content-Type: application/json" \
   -d '{{
-    "sender": "0101010101010101010101010101010101010101010101010101010101010101",
-    "recipient": "0202020202020202020202020202020202020202020202020202020202020202",
+    "chain_id": "{}",
+    "sender": "{}",
+    "recipient": "{}",
     "tx_type": {{ "Transfer": {{ "amount": 10 }} }},
-    "signature": "0000000000000000000000000000000000000000000000000000000000000000"
-}}'"#);
-    // Wait for the servers to finish (which they won't, unless there's an error)
+    "nonce": 1,
+    "fee": {},
+    "signature": "{}"
+}}'"#, hex::encode(example_tx.chain_id.0), hex::encode(example_tx.sender.0), hex::encode(example_tx.recipient.0), example_tx.fee, hex::encode(example_tx.signature.0));
+    // Only the RPC server sends to the core from here on.
+    drop(tx_sender);
+
     tokio::select! {
-        _ = core_handle => eprintln!("[Main] RollupCore unexpectedly shut down."),
-        _ = rpc_handle => eprintln!("[Main] RPC Server unexpectedly shut down."),
+        _ = shutdown_signal() => println!("[Main] Shutdown requested."),
+        _ = &mut core_handle => eprintln!("[Main] RollupCore unexpectedly shut down."),
+        _ = &mut rpc_handle => eprintln!("[Main] RPC Server unexpectedly shut down."),
+    }
+
+    let _ = stop_rpc.send(());
+    let drained = tokio::time::timeout(shutdown_timeout, async {
+        if !rpc_handle.is_finished() { let _ = rpc_handle.await; }
+        // The channel is closed now, so the core seals the final batch and returns.
+        if !core_handle.is_finished() { let _ = (&mut core_handle).await; }
+    }).await;
+    if drained.is_err() {
+        // The core may be inside `commit_batch`; closing storage under it
+        // could leave SQLite behind RocksDB. Leave both as they are: the
+        // next start reconciles them and restores the pending journal.
+        core_handle.abort();
+        return Err(format!("Shutdown did not finish within {:?}; exiting without flushing storage", shutdown_timeout).into());
     }
+    // Both tasks have finished, so nothing else is writing.
+    storage.close().await?;
+    println!("[Main] Storage flushed. Shut down cleanly.");
 
     Ok(())
+}
+
+/// Resolves on SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            eprintln!("[Main] Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => { sigterm.recv().await; }
+            Err(e) => {
+                eprintln!("[Main] Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
\ No newline at end of file
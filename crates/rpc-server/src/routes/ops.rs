@@ -1,10 +1,22 @@
 use crate::state::AppState;
 use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct ConfigResponse {
+    max_txs_per_batch: usize,
+    max_batch_bytes: usize,
+    max_batch_compute: u64,
+    seal_timeout_ms: u64,
+    max_pending_txs: usize,
+    max_pending_per_sender: usize,
+}
 
 pub fn create_router() -> Router<AppState> {
     Router::new()
         .route("/healthz", get(health_check))
         .route("/readyz", get(readiness_check))
+        .route("/configz", get(sequencer_config))
 }
 
 async fn health_check() -> impl IntoResponse {
@@ -19,3 +31,16 @@ async fn readiness_check(State(state): State<AppState>) -> impl IntoResponse {
     }
 }
 
+
+/// The batch limits the sequencer is running with.
+async fn sequencer_config(State(state): State<AppState>) -> Json<ConfigResponse> {
+    let config = state.sequencer_config;
+    Json(ConfigResponse {
+        max_txs_per_batch: config.max_txs_per_batch,
+        max_batch_bytes: config.max_batch_bytes,
+        max_batch_compute: config.max_batch_compute,
+        seal_timeout_ms: config.seal_timeout.as_millis() as u64,
+        max_pending_txs: config.max_pending_txs,
+        max_pending_per_sender: config.max_pending_per_sender,
+    })
+}
@@ -1,13 +1,24 @@
 use crate::{error::ApiError, state::AppState};
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     routing::{get, post},
     Json, Router,
 };
-use rollup_core::types::{Account, BlockHeader, Pubkey, Signature, Transaction, TransactionType};
-use rocksdb::{IteratorMode, OptimisticTransactionDB};
+use rollup_core::db::DbError;
+use rollup_core::sequencer::{AccountProof, CommitmentScheme, ExecutionError, StateTree};
+use rollup_core::types::{Account, AccountKey, BlockHeader, ChainId, Pubkey, Receipt, ReceiptStatus, Signature, Transaction, TransactionType, Withdrawal, MAX_INSTRUCTIONS, NATIVE_MINT, SYSTEM_SENDER};
+use rocksdb::{Direction, IteratorMode};
 use serde::{Deserialize, Serialize};
 
+/// How far past the sender's committed nonce a new transaction may be.
+/// Leaves room for several in-flight transactions from the same sender.
+const MAX_NONCE_AHEAD: u64 = 64;
+
+#[derive(Serialize)]
+struct ChainResponse {
+    chain_id: String,
+}
+
 #[derive(Serialize)]
 struct TipResponse {
     batch_id: u64,
@@ -15,81 +26,344 @@ struct TipResponse {
     flags: u32,
 }
 
+/// Every token balance held by one owner. `nonce` is the owner's next
+/// transaction nonce, tracked on the native account.
+#[derive(Serialize)]
+struct AccountResponse {
+    owner: String,
+    nonce: u64,
+    balances: Vec<TokenBalance>,
+}
+
+#[derive(Serialize)]
+struct TokenBalance {
+    mint: String,
+    balance: u64,
+}
+
+/// Selects the balance to prove and the batch to prove it at; defaults to
+/// the native balance at the tip.
+#[derive(Deserialize)]
+struct ProofQuery {
+    batch: Option<u64>,
+    mint: Option<String>,
+}
+
+/// An `AccountProof` with keys and hashes hex-encoded.
+#[derive(Serialize)]
+struct AccountProofResponse {
+    header: BlockHeader,
+    owner: String,
+    mint: String,
+    /// Null when the proof shows there is no such account at this batch.
+    account: Option<ProvenAccount>,
+    /// Sibling hashes along the account's path, from the root down.
+    siblings: Vec<String>,
+    /// For a missing account whose path ends at another account's leaf:
+    /// that account.
+    other_leaf: Option<ProvenLeaf>,
+}
+
+#[derive(Serialize)]
+struct ProvenAccount {
+    balance: u64,
+    nonce: u64,
+}
+
+#[derive(Serialize)]
+struct ProvenLeaf {
+    owner: String,
+    mint: String,
+    balance: u64,
+    nonce: u64,
+}
+
+impl From<AccountProof> for AccountProofResponse {
+    fn from(proof: AccountProof) -> Self {
+        Self {
+            header: proof.header,
+            owner: hex::encode(proof.key.owner.0),
+            mint: hex::encode(proof.key.mint.0),
+            account: proof.account.map(|account| ProvenAccount { balance: account.balance, nonce: account.nonce }),
+            siblings: proof.proof.siblings.iter().map(hex::encode).collect(),
+            other_leaf: proof.proof.other_leaf.map(|(key, account)| ProvenLeaf {
+                owner: hex::encode(key.owner.0),
+                mint: hex::encode(key.mint.0),
+                balance: account.balance,
+                nonce: account.nonce,
+            }),
+        }
+    }
+}
+
 /// The expected JSON payload for the `send_transaction` endpoint.
 /// Pubkeys and signatures are expected as hex-encoded strings.
 #[derive(Deserialize)]
 struct SendTxRequest {
+    chain_id: String,
     sender: String,
     recipient: String,
     tx_type: TransactionType,
+    nonce: u64,
+    fee: u64,
+    valid_until_batch: Option<u64>,
     signature: String,
 }
 
 #[derive(Serialize)]
 struct SendTxResponse {
     status: &'static str,
-    signature: String,
+    tx_hash: String,
+}
+
+#[derive(Serialize)]
+struct TransactionResponse {
+    tx_hash: String,
+    #[serde(flatten)]
+    tx: Transaction,
+    /// Absent only for transactions committed before receipts were recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    receipt: Option<ReceiptResponse>,
+}
+
+#[derive(Serialize)]
+struct ReceiptResponse {
+    status: ReceiptStatus,
+    error_code: Option<String>,
+    error_message: Option<String>,
+    batch_id: u64,
+    index: u32,
+    balance_changes: Vec<BalanceChangeResponse>,
+}
+
+#[derive(Serialize)]
+struct BalanceChangeResponse {
+    owner: String,
+    mint: String,
+    before: u64,
+    after: u64,
+}
+
+impl From<Receipt> for ReceiptResponse {
+    fn from(receipt: Receipt) -> Self {
+        Self {
+            status: receipt.status,
+            error_code: receipt.error_code,
+            error_message: receipt.error_message,
+            batch_id: receipt.batch_id,
+            index: receipt.index,
+            balance_changes: receipt.balance_changes.into_iter().map(|change| BalanceChangeResponse {
+                owner: hex::encode(change.key.owner.0),
+                mint: hex::encode(change.key.mint.0),
+                before: change.before,
+                after: change.after,
+            }).collect(),
+        }
+    }
+}
+
+/// Cursor for `/v1/withdrawals`: records from `cursor` (a `next` token from an
+/// earlier page) or else from the start of `from_batch`, at most `limit`.
+#[derive(Deserialize)]
+struct WithdrawalsQuery {
+    #[serde(default)]
+    from_batch: u64,
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+/// One page of withdrawals. `next` is the `batch_id:index` of the first
+/// record after this page, absent once the listing is exhausted.
+#[derive(Serialize)]
+struct WithdrawalsPage {
+    withdrawals: Vec<WithdrawalResponse>,
+    next: Option<String>,
 }
 
+#[derive(Serialize)]
+struct WithdrawalResponse {
+    batch_id: u64,
+    index: u32,
+    tx_hash: String,
+    sender: String,
+    l1_recipient: String,
+    mint: String,
+    amount: u64,
+}
+
+const DEFAULT_WITHDRAWALS_LIMIT: usize = 100;
+const MAX_WITHDRAWALS_LIMIT: usize = 1000;
+
 pub fn create_router() -> Router<AppState> {
     Router::new()
+        .route("/chain", get(get_chain))
         .route("/tip", get(get_tip))
         .route("/accounts/{pubkey}", get(get_account))
-        .route("/tx/{signature}", get(get_transaction))
+        .route("/accounts/{pubkey}/proof", get(get_account_proof))
+        .route("/tx/{tx_hash}", get(get_transaction))
         .route("/batches/{id}", get(get_batch))
+        .route("/withdrawals", get(get_withdrawals))
         .route("/send_transaction", post(send_transaction))
 }
 
+/// The chain ID clients must sign their transactions for.
+async fn get_chain(State(state): State<AppState>) -> Json<ChainResponse> {
+    Json(ChainResponse { chain_id: hex::encode(state.chain_id.0) })
+}
+
 async fn get_tip(State(state): State<AppState>) -> Result<Json<TipResponse>, ApiError> {
     let mut iter = state.storage.rocksdb.iterator_cf(state.storage.cf_batches(), IteratorMode::End);
-    match iter.next() {
-        Some(Ok((_, value))) => {
-            let header = BlockHeader::from_bytes(value.as_ref().try_into().map_err(|_| ApiError::DatabaseUnavailable("Invalid header in DB".into()))?)
-                .map_err(|_| ApiError::DatabaseUnavailable("Failed to deserialize header".into()))?;
+    match iter.next().transpose()? {
+        Some((_, value)) => {
+            let header = decode_header(&value)?;
             Ok(Json(TipResponse { batch_id: header.batch_id, new_root: hex::encode(header.new_root), flags: header.flags }))
         }
-        _ => Err(ApiError::NotFound("Chain is empty; no tip found".to_string())),
+        None => Err(ApiError::NotFound("Chain is empty; no tip found".to_string())),
     }
 }
 
-async fn get_account(State(state): State<AppState>, Path(pubkey_hex): Path<String>) -> Result<Json<Account>, ApiError> {
-    println!("hi");
+async fn get_account(State(state): State<AppState>, Path(pubkey_hex): Path<String>) -> Result<Json<AccountResponse>, ApiError> {
     if pubkey_hex.len() != 64 { return Err(ApiError::BadRequest("Public key must be a 64-character hex string.".into())); }
-    println!("Fetching account: {}", pubkey_hex);
-    let pubkey_bytes = hex::decode(pubkey_hex).map_err(|_| ApiError::BadRequest("Invalid hex characters in public key.".into()))?;
-    match state.storage.rocksdb.get_cf(state.storage.cf_accounts(), &pubkey_bytes) {
-        Ok(Some(bytes)) => Ok(Json(bincode::deserialize(&bytes).map_err(|_| ApiError::DatabaseUnavailable("Failed to deserialize account.".into()))?)),
-        Ok(None) => Err(ApiError::NotFound("Account not found.".into())),
-        Err(e) => Err(ApiError::DatabaseUnavailable(format!("DB error: {}", e))),
+    let owner = hex::decode(&pubkey_hex).map_err(|_| ApiError::BadRequest("Invalid hex characters in public key.".into()))?;
+
+    // Keys are `owner || mint`, so an owner's balances are one contiguous range.
+    let mut response = AccountResponse { owner: pubkey_hex, nonce: 0, balances: Vec::new() };
+    let iter = state.storage.rocksdb.iterator_cf(state.storage.cf_accounts(), IteratorMode::From(&owner, Direction::Forward));
+    for item in iter {
+        let (key, value) = item?;
+        if !key.starts_with(&owner) { break; }
+        let key = AccountKey::from_bytes(&key).ok_or_else(|| DbError::Corrupt("Invalid account key in DB".into()))?;
+        let account: Account = bincode::deserialize(&value)?;
+        if key.mint == NATIVE_MINT { response.nonce = account.nonce; }
+        response.balances.push(TokenBalance { mint: hex::encode(key.mint.0), balance: account.balance });
     }
+
+    if response.balances.is_empty() { return Err(ApiError::NotFound("Account not found.".into())); }
+    Ok(Json(response))
 }
 
-async fn get_transaction(State(state): State<AppState>, Path(signature_hex): Path<String>) -> Result<Json<Transaction>, ApiError> {
-    if signature_hex.len() != 64 { return Err(ApiError::BadRequest("Signature must be a 64-character hex string.".into())); }
-    let sig_bytes = hex::decode(signature_hex).map_err(|_| ApiError::BadRequest("Invalid hex characters in signature.".into()))?;
-    match state.storage.rocksdb.get_cf(state.storage.cf_txs(), &sig_bytes) {
-        Ok(Some(bytes)) => Ok(Json(bincode::deserialize(&bytes).map_err(|_| ApiError::DatabaseUnavailable("Failed to deserialize transaction.".into()))?)),
-        Ok(None) => Err(ApiError::NotFound("Transaction not found.".into())),
-        Err(e) => Err(ApiError::DatabaseUnavailable(format!("DB error: {}", e))),
+/// Proves one balance, or its absence, against the state root of a
+/// committed batch.
+async fn get_account_proof(State(state): State<AppState>, Path(pubkey_hex): Path<String>, Query(query): Query<ProofQuery>) -> Result<Json<AccountProofResponse>, ApiError> {
+    let owner = decode_pubkey(&pubkey_hex, "Public key")?;
+    let mint = match &query.mint {
+        Some(mint_hex) => decode_pubkey(mint_hex, "Mint")?,
+        None => NATIVE_MINT,
+    };
+    let header = match query.batch {
+        Some(id) => match state.storage.rocksdb.get_cf(state.storage.cf_batches(), id.to_be_bytes())? {
+            Some(bytes) => decode_header(&bytes)?,
+            None => return Err(ApiError::NotFound(format!("Batch with ID {} not found.", id))),
+        },
+        None => match state.storage.rocksdb.iterator_cf(state.storage.cf_batches(), IteratorMode::End).next().transpose()? {
+            Some((_, value)) => decode_header(&value)?,
+            None => return Err(ApiError::NotFound("Chain is empty; no tip found".to_string())),
+        },
+    };
+
+    if CommitmentScheme::from_flags(header.flags) != Some(CommitmentScheme::SparseMerkle) {
+        return Err(ApiError::NotFound(format!("Batch {} is not committed to a state tree and cannot be proven.", header.batch_id)));
+    }
+    let mut tree = StateTree::new(&state.storage);
+    if !tree.contains(&header.new_root)? {
+        return Err(ApiError::NotFound(format!("Batch {} was committed before the state tree and cannot be proven.", header.batch_id)));
     }
+    let key = AccountKey::new(owner, mint);
+    let (account, proof) = tree.prove(&header.new_root, &key)?;
+    Ok(Json(AccountProof { header, key, account, proof }.into()))
+}
+
+fn decode_pubkey(value: &str, what: &str) -> Result<Pubkey, ApiError> {
+    let bytes = hex::decode(value).map_err(|_| ApiError::BadRequest(format!("Invalid hex characters in {}.", what.to_lowercase())))?;
+    let bytes = bytes.try_into().map_err(|_| ApiError::BadRequest(format!("{} must be a 64-character hex string.", what)))?;
+    Ok(Pubkey(bytes))
+}
+
+async fn get_transaction(State(state): State<AppState>, Path(tx_hash_hex): Path<String>) -> Result<Json<TransactionResponse>, ApiError> {
+    if tx_hash_hex.len() != 64 { return Err(ApiError::BadRequest("Transaction hash must be a 64-character hex string.".into())); }
+    let hash_bytes = hex::decode(&tx_hash_hex).map_err(|_| ApiError::BadRequest("Invalid hex characters in transaction hash.".into()))?;
+    let tx = match state.storage.rocksdb.get_cf(state.storage.cf_txs(), &hash_bytes)? {
+        Some(bytes) => bincode::deserialize(&bytes)?,
+        None => return Err(ApiError::NotFound("Transaction not found.".into())),
+    };
+    let receipt = match state.storage.rocksdb.get_cf(state.storage.cf_receipts(), &hash_bytes)? {
+        Some(bytes) => Some(bincode::deserialize::<Receipt>(&bytes)?.into()),
+        None => None,
+    };
+    Ok(Json(TransactionResponse { tx_hash: tx_hash_hex, tx, receipt }))
+}
+
+fn decode_header(bytes: &[u8]) -> Result<BlockHeader, DbError> {
+    BlockHeader::from_bytes(bytes).map_err(|e| DbError::Corrupt(format!("Invalid header in DB: {}", e)))
 }
 
 async fn get_batch(State(state): State<AppState>, Path(id): Path<u64>) -> Result<Json<BlockHeader>, ApiError> {
-    match state.storage.rocksdb.get_cf(state.storage.cf_batches(), id.to_be_bytes()) {
-        Ok(Some(bytes)) => {
-            let header = BlockHeader::from_bytes(bytes.as_slice().try_into().map_err(|_| ApiError::DatabaseUnavailable("Invalid header in DB".into()))?)
-                .map_err(|_| ApiError::DatabaseUnavailable("Failed to deserialize header".into()))?;
-            Ok(Json(header))
+    match state.storage.rocksdb.get_cf(state.storage.cf_batches(), id.to_be_bytes())? {
+        Some(bytes) => Ok(Json(decode_header(&bytes)?)),
+        None => Err(ApiError::NotFound(format!("Batch with ID {} not found.", id))),
+    }
+}
+
+/// Parses a `batch_id:index` withdrawals cursor into its column family key.
+fn parse_withdrawals_cursor(cursor: &str) -> Result<[u8; 12], ApiError> {
+    let invalid = || ApiError::BadRequest("Cursor must be a `batch_id:index` token from a previous page.".into());
+    let (batch_id, index) = cursor.split_once(':').ok_or_else(invalid)?;
+    let batch_id: u64 = batch_id.parse().map_err(|_| invalid())?;
+    let index: u32 = index.parse().map_err(|_| invalid())?;
+    let mut key = [0u8; 12];
+    key[..8].copy_from_slice(&batch_id.to_be_bytes());
+    key[8..].copy_from_slice(&index.to_be_bytes());
+    Ok(key)
+}
+
+/// Lists withdrawals in batch order so the bridge can settle them on L1.
+async fn get_withdrawals(State(state): State<AppState>, Query(query): Query<WithdrawalsQuery>) -> Result<Json<WithdrawalsPage>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_WITHDRAWALS_LIMIT).clamp(1, MAX_WITHDRAWALS_LIMIT);
+    let start = match &query.cursor {
+        Some(cursor) => parse_withdrawals_cursor(cursor)?.to_vec(),
+        None => query.from_batch.to_be_bytes().to_vec(),
+    };
+    let iter = state.storage.rocksdb.iterator_cf(state.storage.cf_withdrawals(), IteratorMode::From(&start, Direction::Forward));
+
+    let mut withdrawals = Vec::new();
+    let mut next = None;
+    for item in iter {
+        let (key, value) = item?;
+        let key: [u8; 12] = key.as_ref().try_into().map_err(|_| DbError::Corrupt("Invalid withdrawal key in DB".into()))?;
+        let batch_id = u64::from_be_bytes(key[..8].try_into().unwrap());
+        let index = u32::from_be_bytes(key[8..].try_into().unwrap());
+        if withdrawals.len() == limit {
+            next = Some(format!("{}:{}", batch_id, index));
+            break;
         }
-        Ok(None) => Err(ApiError::NotFound(format!("Batch with ID {} not found.", id))),
-        Err(e) => Err(ApiError::DatabaseUnavailable(format!("DB error: {}", e))),
+        let withdrawal: Withdrawal = bincode::deserialize(&value)?;
+        withdrawals.push(WithdrawalResponse {
+            batch_id,
+            index,
+            tx_hash: hex::encode(withdrawal.tx_hash.0),
+            sender: hex::encode(withdrawal.sender.0),
+            l1_recipient: hex::encode(withdrawal.l1_recipient.0),
+            mint: hex::encode(withdrawal.mint.0),
+            amount: withdrawal.amount,
+        });
     }
+    Ok(Json(WithdrawalsPage { withdrawals, next }))
 }
 
 /// Receives a transaction, validates it, and forwards it to the Rollup Core's mempool.
+/// "queued" means the mempool admitted it, not that a batch will include it:
+/// it can still be replaced by a higher fee, evicted, or fail execution, so
+/// clients poll `/v1/tx/{hash}` for the receipt.
+///
+/// `SYSTEM_SENDER` is refused: its deposits are unsigned and mint funds, so
+/// they only enter through `deposits::submit_deposit`, inside the node.
 async fn send_transaction(State(state):State<AppState>,Json(payload): Json<SendTxRequest>)->Result<Json<SendTxResponse>,ApiError>{
-    println!("recieved");
-// 1. Validate and decode hex-encoded fields.
+    // 1. Validate and decode hex-encoded fields.
+    let chain_id = ChainId::from_hex(&payload.chain_id)
+        .ok_or_else(|| ApiError::BadRequest("Chain ID must be a 64-character hex string.".to_string()))?;
+    if chain_id != state.chain_id {
+        return Err(ExecutionError::WrongChain.into());
+    }
     let sender_bytes = hex::decode(&payload.sender)
         .map_err(|_| ApiError::BadRequest("Invalid hex for sender pubkey.".to_string()))?;
     let recipient_bytes = hex::decode(&payload.recipient)
@@ -97,62 +371,20 @@ async fn send_transaction(State(state):State<AppState>,Json(payload): Json<SendT
     let signature_bytes = hex::decode(&payload.signature)
         .map_err(|_| ApiError::BadRequest("Invalid hex for signature.".to_string()))?;
 
-    //system level deposit from null address
-    if sender_bytes == [0;32]{
-        println!("[API] Detected system deposit for recipient: {}", payload.recipient);
-        // This is a "mint" (deposit), not a "transfer".
-        // We will update the database directly instead of sending to the sequencer.
-        let amount = match payload.tx_type {
-            TransactionType::Deposit { amount } => amount,
-            _ => return Err(ApiError::BadRequest("System deposit must have tx_type 'Deposit'".into())),
-        };
-
-        // Get a reference to the DB and the account column family
-        let db = &state.storage.rocksdb;
-        let cf_accounts = state.storage.cf_accounts();
-        let recipient_pubkey = Pubkey(recipient_bytes.try_into().map_err(|_| {
-            ApiError::BadRequest("Recipient pubkey must be 32 bytes.".to_string())
-        })?);
-        // Fetch or create the account, update balance
-        let mut updated_account: Account;
-
-        match db.get_cf(cf_accounts, &recipient_pubkey.0) {
-            Ok(Some(bytes)) => {
-                let mut account: Account = bincode::deserialize(&bytes)
-                    .map_err(|_| ApiError::DatabaseUnavailable("Failed to deserialize account.".into()))?;
-                account.balance = account.balance.checked_add(amount)
-                    .ok_or(ApiError::DatabaseUnavailable("Balance overflow".into()))?;
-                account.nonce += 1;
-                updated_account = account;
-            }
-            Ok(None) => {
-                updated_account = Account {
-                    balance: amount,
-                    nonce: 1,
-                };
-            }
-            Err(e) => return Err(ApiError::DatabaseUnavailable(format!("DB error: {}", e))),
-        }
-
-        // Save the updated account
-        let updated_bytes = bincode::serialize(&updated_account)
-            .map_err(|_| ApiError::DatabaseUnavailable("Failed to serialize account.".into()))?;
-        db.put_cf(cf_accounts, &recipient_pubkey.0, updated_bytes).unwrap();
-
-        println!(
-            "[API] Successfully credited {} with {} zSOL",
-            payload.recipient, amount
-        );
+    // Deposits from the system sender are unsigned; they come from the L1
+    // bridge, never from clients.
+    if sender_bytes == SYSTEM_SENDER.0 {
+        return Err(ApiError::BadRequest("The system sender cannot submit transactions over RPC.".to_string()));
+    }
 
-        // Respond immediately — already processed.
-        return Ok(Json(SendTxResponse {
-            status: "processed",
-            signature: payload.signature,
-        }));
+    // Only the system sender mints; a signed user deposit would create funds.
+    if let TransactionType::Deposit { .. } = payload.tx_type {
+        return Err(ApiError::BadRequest("Only the system sender can deposit.".to_string()));
     }
 
     // 2. Construct the core Transaction type.
     let tx = Transaction {
+        chain_id,
         sender: Pubkey(sender_bytes.try_into().map_err(|_| {
             ApiError::BadRequest("Sender pubkey must be 32 bytes.".to_string())
         })?),
@@ -160,20 +392,51 @@ async fn send_transaction(State(state):State<AppState>,Json(payload): Json<SendT
             ApiError::BadRequest("Recipient pubkey must be 32 bytes.".to_string())
         })?),
         tx_type: payload.tx_type,
+        nonce: payload.nonce,
+        fee: payload.fee,
+        valid_until_batch: payload.valid_until_batch,
         signature: Signature(signature_bytes.try_into().map_err(|_| {
-            ApiError::BadRequest("Signature must be 32 bytes.".to_string())
+            ApiError::BadRequest("Signature must be 64 bytes.".to_string())
         })?),
     };
+    if !tx.verify_signature() {
+        return Err(ApiError::InvalidSignature("Signature does not match sender.".to_string()));
+    }
+    if let TransactionType::Multi { instructions } = &tx.tx_type
+        && !(1..=MAX_INSTRUCTIONS).contains(&instructions.len())
+    {
+        return Err(ExecutionError::InvalidInstructionCount { count: instructions.len() }.into());
+    }
+    if let Some(valid_until_batch) = tx.valid_until_batch {
+        let next_batch = match state.storage.rocksdb.iterator_cf(state.storage.cf_batches(), IteratorMode::End).next().transpose()? {
+            Some((_, value)) => decode_header(&value)?.batch_id + 1,
+            None => 1,
+        };
+        if next_batch > valid_until_batch {
+            return Err(ExecutionError::Expired { valid_until_batch, batch_id: next_batch }.into());
+        }
+    }
+
+    // Reject nonces the sequencer can never accept. Anything in the window is
+    // queued, since earlier transactions from this sender may still be pending.
+    let current_nonce = match state.storage.rocksdb.get_cf(state.storage.cf_accounts(), AccountKey::native(tx.sender).to_bytes())? {
+        Some(bytes) => bincode::deserialize::<Account>(&bytes)?.nonce,
+        None => 0,
+    };
+    if tx.nonce < current_nonce {
+        return Err(ApiError::NonceTooLow(format!("Nonce {} already used; next nonce is {}.", tx.nonce, current_nonce)));
+    }
+    if tx.nonce > current_nonce + MAX_NONCE_AHEAD {
+        return Err(ApiError::NonceTooHigh(format!("Nonce {} is more than {} ahead of next nonce {}.", tx.nonce, MAX_NONCE_AHEAD, current_nonce)));
+    }
 
     // 3. Send the transaction to the Rollup Core.
-    state
-        .tx_sender
-        .send(tx)
-        .await.map_err(|_| ApiError::DatabaseUnavailable("Sequencer channel is closed.".to_string()))?;
+    let tx_hash = tx.hash();
+    state.enqueue(tx).await?;
 
     // 4. Respond with acceptance.
     Ok(Json(SendTxResponse {
         status: "queued",
-        signature: payload.signature,
+        tx_hash: hex::encode(tx_hash.0),
     }))
 }
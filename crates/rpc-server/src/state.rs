@@ -1,10 +1,35 @@
-use rollup_core::{db::Storage, types::Transaction};
+use crate::error::ApiError;
+use rollup_core::{db::{forget_pending, journal_pending, Storage}, sequencer::{SequencerConfig, Submission}, types::{ChainId, Transaction}};
 use std::sync::Arc;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::{mpsc::Sender, oneshot};
 
 #[derive(Clone)]
 pub struct AppState {
     pub storage: Arc<Storage>,
-    pub tx_sender : Sender<Transaction>
+    pub tx_sender : Sender<Submission>,
+    pub chain_id: ChainId,
+    /// The limits the sequencer was started with, reported by `/configz`.
+    pub sequencer_config: SequencerConfig,
 }
 
+impl AppState {
+    /// Journals `tx` and hands it to the Rollup Core, waiting for the mempool to
+    /// admit it. A transaction answered as queued is restored into the mempool
+    /// if the sequencer restarts first; the core forgets one it turns away.
+    pub async fn enqueue(&self, tx: Transaction) -> Result<(), ApiError> {
+        let tx_hash = tx.hash();
+        journal_pending(&self.storage, &tx)?;
+        let (reply, admitted) = oneshot::channel();
+        if self.tx_sender.send(Submission { tx, reply: Some(reply) }).await.is_err() {
+            forget_pending(&self.storage, [tx_hash])?;
+            return Err(ApiError::SequencerUnavailable("Sequencer channel is closed.".to_string()));
+        }
+        match admitted.await {
+            Ok(admitted) => Ok(admitted?),
+            Err(_) => {
+                forget_pending(&self.storage, [tx_hash])?;
+                Err(ApiError::SequencerUnavailable("Sequencer stopped before admitting the transaction.".to_string()))
+            }
+        }
+    }
+}
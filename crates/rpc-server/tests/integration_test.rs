@@ -1,17 +1,48 @@
-use axum::{response::Json, serve};
+use axum::serve;
+use ed25519_dalek::SigningKey;
 use rollup_core::{
-    db::{reconcile_databases_on_startup, Storage},
-    sequencer::RollupCore,
-    types::{Account, BlockHeader, Pubkey, Signature, Transaction, TransactionType},
+    db::{reconcile_databases_on_startup, DbError, Storage},
+    sequencer::{compute_state_commitment, partition, BatchContext, compute_state_commitment_parallel, merkle_root, poseidon2, AccountProof, AccountsFold, CommitmentScheme, CommitmentUpdate, ExecutionError, FeeConfig, Mempool, MempoolError, MerkleProof, RollupCore, SequencerConfig, SequencerError, StateCommitment, StateProof, StateTree, Submission, EMPTY_ROOT},
+    types::{Account, AccountKey, BlockHeader, ChainId, Instruction, Pubkey, Receipt, ReceiptStatus, Signature, Transaction, TransactionType, TxHash, NATIVE_MINT, SYSTEM_SENDER},
 };
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tempfile::tempdir;
+use tempfile::{tempdir, TempDir};
 use tokio::sync::mpsc;
-use rpc_server::{routes::create_router, state::AppState};
+use tokio::time::{Duration, Instant};
+use rpc_server::{bridge::{check_chain_id, BridgeConfig}, deposits::submit_deposit, error::ApiError, routes::create_router, state::AppState};
 
-async fn spawn_app()->(SocketAddr,mpsc::Sender<Transaction>,Arc<Storage>){
+async fn spawn_app()->(SocketAddr,mpsc::Sender<Submission>,TestStorage){
+    spawn_app_with_fees(test_fees()).await
+}
+
+async fn spawn_app_with_fees(fees: FeeConfig)->(SocketAddr,mpsc::Sender<Submission>,TestStorage){
+    spawn_app_on(temp_storage().await, fees, CommitmentScheme::default()).await
+}
+
+/// Storage in a temporary directory, which is removed once the test drops
+/// its last handle. The SQLite pool opens connections lazily, so the
+/// directory has to outlive `temp_storage`.
+#[derive(Clone)]
+struct TestStorage {
+    storage: Arc<Storage>,
+    _dir: Arc<TempDir>,
+}
+
+impl Deref for TestStorage {
+    type Target = Arc<Storage>;
+
+    fn deref(&self) -> &Arc<Storage> {
+        &self.storage
+    }
+}
+
+async fn temp_storage() -> TestStorage {
     let temp_dir = tempdir().unwrap();
     let rocks_path = temp_dir.path().join("rocksdb");
     let sqlite_path = temp_dir.path().join("checkpoints.db");
@@ -25,16 +56,39 @@ async fn spawn_app()->(SocketAddr,mpsc::Sender<Transaction>,Arc<Storage>){
         .unwrap(),
     );
     reconcile_databases_on_startup(&storage).await.unwrap();
+    TestStorage { storage, _dir: Arc::new(temp_dir) }
+}
+
+/// No minimum fee, collected by an account no test sends from.
+fn test_fees() -> FeeConfig {
+    FeeConfig { collector: Pubkey::new([99; 32]), min_fee: 0 }
+}
+
+/// Batches of 5, which the tests fill to seal them without waiting.
+fn test_sequencer_config() -> SequencerConfig {
+    SequencerConfig { max_txs_per_batch: 5, ..SequencerConfig::default() }
+}
+
+/// The RPC state for a sequencer fed by `tx_sender`.
+fn app_state(storage: &Arc<Storage>, tx_sender: mpsc::Sender<Submission>) -> AppState {
+    AppState {
+        storage: Arc::clone(storage),
+        tx_sender,
+        chain_id: ChainId::DEVNET,
+        sequencer_config: test_sequencer_config(),
+    }
+}
 
+/// Starts the sequencer and RPC server on existing storage.
+async fn spawn_app_on(storage: TestStorage, fees: FeeConfig, commitment: CommitmentScheme)->(SocketAddr,mpsc::Sender<Submission>,TestStorage){
     let (tx_sender, tx_receiver) = mpsc::channel(100);
 
     let core_storage = Arc::clone(&storage);
-    let rollup_core = RollupCore::new(core_storage, tx_receiver).await.unwrap();
+    let rollup_core = RollupCore::new(core_storage, tx_receiver, test_sequencer_config(), fees, ChainId::DEVNET).await.unwrap()
+        .with_commitment(commitment.build()).unwrap();
     tokio::spawn(rollup_core.run());
 
-    let rpc_state = AppState {
-        storage: Arc::clone(&storage),
-    };
+    let rpc_state = app_state(&storage, tx_sender.clone());
 
     let port = portpicker::pick_unused_port().expect("No free ports");
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
@@ -45,6 +99,90 @@ async fn spawn_app()->(SocketAddr,mpsc::Sender<Transaction>,Arc<Storage>){
     (addr, tx_sender, storage)
 }
 
+fn seed_account(storage: &Storage, key: AccountKey, balance: u64) {
+    storage.rocksdb.put_cf(storage.cf_accounts(), key.to_bytes(), bincode::serialize(&Account { balance, nonce: 0 }).unwrap()).unwrap();
+}
+
+/// Reads an owner's native balance and nonce from `/v1/accounts`.
+async fn fetch_native_account(client: &reqwest::Client, base_url: &str, owner: Pubkey) -> Account {
+    let res = client.get(format!("{}/v1/accounts/{}", base_url, hex::encode(owner.0))).send().await.unwrap();
+    assert_eq!(res.status(), 200);
+    let body: Value = res.json().await.unwrap();
+    let native = body["balances"].as_array().unwrap().iter()
+        .find(|b| b["mint"] == hex::encode(NATIVE_MINT.0))
+        .map_or(0, |b| b["balance"].as_u64().unwrap());
+    Account { balance: native, nonce: body["nonce"].as_u64().unwrap() }
+}
+
+fn signed_transfer(key: &SigningKey, recipient: Pubkey, amount: u64, nonce: u64) -> Transaction {
+    signed_transfer_with_fee(key, recipient, amount, nonce, 0)
+}
+
+fn signed_transfer_with_fee(key: &SigningKey, recipient: Pubkey, amount: u64, nonce: u64, fee: u64) -> Transaction {
+    let mut tx = Transaction {
+        chain_id: ChainId::DEVNET,
+        sender: Pubkey::new(key.verifying_key().to_bytes()),
+        recipient,
+        tx_type: TransactionType::Transfer { amount, mint: NATIVE_MINT },
+        nonce,
+        fee,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    tx.sign(key);
+    tx
+}
+
+/// How long a test waits for the sequencer before failing.
+const WAIT_LIMIT: Duration = Duration::from_secs(5);
+
+/// Posts `tx` to `/v1/send_transaction` in the JSON shape clients send.
+async fn submit(client: &reqwest::Client, base_url: &str, tx: &Transaction) -> reqwest::Response {
+    client
+        .post(format!("{}/v1/send_transaction", base_url))
+        .json(&json!({
+            "chain_id": hex::encode(tx.chain_id.0),
+            "sender": hex::encode(tx.sender.0),
+            "recipient": hex::encode(tx.recipient.0),
+            "tx_type": tx.tx_type,
+            "nonce": tx.nonce,
+            "fee": tx.fee,
+            "valid_until_batch": tx.valid_until_batch,
+            "signature": hex::encode(tx.signature.0),
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Polls `/v1/tx/{hash}` until the transaction has a receipt, and returns it.
+async fn wait_for_receipt(client: &reqwest::Client, base_url: &str, tx_hash: TxHash) -> Value {
+    let url = format!("{}/v1/tx/{}", base_url, hex::encode(tx_hash.0));
+    let deadline = Instant::now() + WAIT_LIMIT;
+    loop {
+        let res = client.get(&url).send().await.unwrap();
+        if res.status() == 200 {
+            let stored: Value = res.json().await.unwrap();
+            if !stored["receipt"].is_null() {
+                return stored;
+            }
+        }
+        assert!(Instant::now() < deadline, "no receipt at {} after {:?}", url, WAIT_LIMIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Polls storage until batch `batch_id` is committed, for tests without an RPC server.
+async fn wait_for_batch(storage: &Storage, batch_id: u64) -> BlockHeader {
+    let deadline = Instant::now() + WAIT_LIMIT;
+    loop {
+        if let Some(bytes) = storage.rocksdb.get_cf(storage.cf_batches(), batch_id.to_be_bytes()).unwrap() {
+            return BlockHeader::from_bytes(&bytes).unwrap();
+        }
+        assert!(Instant::now() < deadline, "batch {} not committed after {:?}", batch_id, WAIT_LIMIT);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
 
 #[tokio::test]
 async fn test_happy_path_genesis_and_first_block() {
@@ -54,39 +192,28 @@ async fn test_happy_path_genesis_and_first_block() {
 
     assert_eq!(client.get(format!("{}/v1/tip", base_url)).send().await.unwrap().status(), 404);
 
-    let mut initial_batch = rocksdb::WriteBatch::default();
-    let (acc1_pk, acc2_pk) = (Pubkey::new([1; 32]), Pubkey::new([2; 32]));
-    initial_batch.put_cf(storage.cf_accounts(), &acc1_pk.0, &bincode::serialize(&Account { balance: 1000, nonce: 0 }).unwrap());
-    storage.rocksdb.write(initial_batch).unwrap();
-    
-    // FIX: Use a 32-byte signature
-    let tx = Transaction {
-        sender: acc1_pk,
-        recipient: acc2_pk,
-        tx_type: TransactionType::Transfer { amount: 100 },
-        signature: Signature([5; 32]),
-    };
-    tx_sender.send(tx.clone()).await.unwrap();
-    for i in 0..4 {
-        tx_sender.send(Transaction { sender: acc1_pk, recipient: acc2_pk, tx_type: TransactionType::Transfer { amount: 1 }, signature: Signature([i; 32]) }).await.unwrap();
+    let acc1_key = SigningKey::from_bytes(&[1; 32]);
+    let (acc1_pk, acc2_pk) = (Pubkey::new(acc1_key.verifying_key().to_bytes()), Pubkey::new([2; 32]));
+    seed_account(&storage, AccountKey::native(acc1_pk), 1000);
+
+    let tx = signed_transfer(&acc1_key, acc2_pk, 100, 0);
+    tx_sender.send(tx.clone().into()).await.unwrap();
+    for nonce in 1..5 {
+        tx_sender.send(signed_transfer(&acc1_key, acc2_pk, 1, nonce).into()).await.unwrap();
     }
-    
-    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+    let stored = wait_for_receipt(&client, &base_url, tx.hash()).await;
+    assert_eq!(stored["tx_hash"], hex::encode(tx.hash().0));
+    assert_eq!(stored["nonce"], 0);
 
     let res = client.get(format!("{}/v1/tip", base_url)).send().await.unwrap();
     assert_eq!(res.status(), 200);
     let tip: Value = res.json().await.unwrap();
     assert_eq!(tip["batch_id"], 1);
 
-    let res = client.get(format!("{}/v1/accounts/{}", base_url, hex::encode(acc1_pk.0))).send().await.unwrap();
-    let account: Account = res.json().await.unwrap();
+    let account = fetch_native_account(&client, &base_url, acc1_pk).await;
     assert_eq!(account.balance, 896);
     assert_eq!(account.nonce, 5);
 
-    // FIX: URL uses 64-char hex string for the 32-byte signature
-    let res = client.get(format!("{}/v1/tx/{}", base_url, hex::encode(tx.signature.0))).send().await.unwrap();
-    assert_eq!(res.status(), 200);
-
     let res = client.get(format!("{}/v1/batches/1", base_url)).send().await.unwrap();
     assert_eq!(res.status(), 200);
     let header: Value = res.json().await.unwrap();
@@ -113,4 +240,1418 @@ async fn test_bad_input_validation() {
     // Test tx with wrong length
     let res = client.get(format!("{}/v1/tx/010203", base_url)).send().await.unwrap();
     assert_eq!(res.status(), 400);
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_send_transaction_rejects_bad_signature() {
+    let (addr, _, _) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let mut tx = signed_transfer(&key, Pubkey::new([2; 32]), 10, 0);
+    tx.signature.0[0] ^= 0xff;
+
+    let res = submit(&client, &base_url, &tx).await;
+    assert_eq!(res.status(), 401);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "invalid_signature");
+}
+
+#[tokio::test]
+async fn test_nonces_are_ordered_and_replays_rejected() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[3; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([4; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    // Arrival order is reversed; the sequencer must still apply them by nonce.
+    let txs: Vec<Transaction> = (0..5).map(|nonce| signed_transfer(&key, recipient, 1, nonce)).collect();
+    for tx in txs.iter().rev() {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, txs[4].hash()).await;
+
+    let account = fetch_native_account(&client, &base_url, sender).await;
+    assert_eq!(account.balance, 995);
+    assert_eq!(account.nonce, 5);
+
+    let res = submit(&client, &base_url, &txs[0]).await;
+    assert_eq!(res.status(), 409);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "nonce_too_low");
+
+    let res = submit(&client, &base_url, &signed_transfer(&key, recipient, 1, 500)).await;
+    assert_eq!(res.status(), 409);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "nonce_too_high");
+
+    let res = submit(&client, &base_url, &signed_transfer(&key, recipient, 1, 5)).await;
+    assert_eq!(res.status(), 200);
+}
+
+#[tokio::test]
+async fn test_fees_are_charged_to_sender_and_credited_to_collector() {
+    let collector = Pubkey::new([9; 32]);
+    let (addr, tx_sender, storage) = spawn_app_with_fees(FeeConfig { collector, min_fee: 2 }).await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[5; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    // Three transfers pay 2 each. The fourth overdraws but still pays its fee;
+    // the fifth is below the minimum fee and is rejected outright.
+    let recipient = Pubkey::new([6; 32]);
+    let mut txs: Vec<Transaction> = (0..3).map(|nonce| signed_transfer_with_fee(&key, recipient, 10, nonce, 2)).collect();
+    txs.push(signed_transfer_with_fee(&key, recipient, 5000, 3, 2));
+    txs.push(signed_transfer_with_fee(&key, recipient, 10, 4, 1));
+    for tx in &txs {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+    let stored = wait_for_receipt(&client, &base_url, txs[0].hash()).await;
+    assert_eq!(stored["fee"], 2);
+
+    let account = fetch_native_account(&client, &base_url, sender).await;
+    assert_eq!(account.balance, 1000 - 3 * 10 - 4 * 2);
+    assert_eq!(account.nonce, 4);
+
+    let collected = fetch_native_account(&client, &base_url, collector).await;
+    assert_eq!(collected.balance, 8);
+}
+
+#[tokio::test]
+async fn test_fee_collector_can_spend_its_fees() {
+    let collector_key = SigningKey::from_bytes(&[50; 32]);
+    let collector = Pubkey::new(collector_key.verifying_key().to_bytes());
+    let (addr, tx_sender, storage) = spawn_app_with_fees(FeeConfig { collector, min_fee: 3 }).await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[51; 32]);
+    seed_account(&storage, AccountKey::native(Pubkey::new(key.verifying_key().to_bytes())), 100);
+    let recipient = Pubkey::new([52; 32]);
+    let txs: Vec<Transaction> = (0..5).map(|nonce| signed_transfer_with_fee(&key, recipient, 1, nonce, 3)).collect();
+    for tx in &txs {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, txs[4].hash()).await;
+    assert_eq!(fetch_native_account(&client, &base_url, collector).await.balance, 15);
+
+    // Its own fee comes straight back to it.
+    let spend = signed_transfer_with_fee(&collector_key, recipient, 12, 0, 3);
+    tx_sender.send(spend.clone().into()).await.unwrap();
+    wait_for_receipt(&client, &base_url, spend.hash()).await;
+    assert_eq!(fetch_native_account(&client, &base_url, collector).await, Account { balance: 3, nonce: 1 });
+    assert_eq!(fetch_native_account(&client, &base_url, recipient).await.balance, 5 + 12);
+}
+
+#[tokio::test]
+async fn test_system_sender_cannot_collect_fees() {
+    let (_tx_sender, tx_receiver) = mpsc::channel(1);
+    let fees = FeeConfig { collector: SYSTEM_SENDER, min_fee: 0 };
+    let storage = temp_storage().await;
+    let result = RollupCore::new(Arc::clone(&storage), tx_receiver, test_sequencer_config(), fees, ChainId::DEVNET).await;
+    assert!(matches!(result, Err(SequencerError::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn test_withdrawal_burns_balance_and_is_listed() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[8; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let l1_recipient = Pubkey::new([0xaa; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    let mut withdraw = Transaction {
+        chain_id: ChainId::DEVNET,
+        sender,
+        recipient: sender,
+        tx_type: TransactionType::Withdraw { amount: 400, l1_recipient, mint: NATIVE_MINT },
+        nonce: 0,
+        fee: 0,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    withdraw.sign(&key);
+    tx_sender.send(withdraw.clone().into()).await.unwrap();
+    for nonce in 1..5 {
+        tx_sender.send(signed_transfer(&key, Pubkey::new([2; 32]), 1, nonce).into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, withdraw.hash()).await;
+
+    let account = fetch_native_account(&client, &base_url, sender).await;
+    assert_eq!(account.balance, 1000 - 400 - 4);
+
+    let res = client.get(format!("{}/v1/withdrawals?from_batch=1", base_url)).send().await.unwrap();
+    assert_eq!(res.status(), 200);
+    let page: Value = res.json().await.unwrap();
+    let withdrawals = page["withdrawals"].as_array().unwrap();
+    assert_eq!(withdrawals.len(), 1);
+    assert_eq!(withdrawals[0]["batch_id"], 1);
+    assert_eq!(withdrawals[0]["tx_hash"], hex::encode(withdraw.hash().0));
+    assert_eq!(withdrawals[0]["l1_recipient"], hex::encode(l1_recipient.0));
+    assert_eq!(withdrawals[0]["amount"], 400);
+    assert!(page["next"].is_null());
+
+    let later: Value = client.get(format!("{}/v1/withdrawals?from_batch=2", base_url)).send().await.unwrap().json().await.unwrap();
+    assert!(later["withdrawals"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_withdrawals_page_within_one_batch() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[9; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    let instructions = (1..=5u8).map(|i| Instruction::Withdraw { amount: i as u64, l1_recipient: Pubkey::new([i; 32]), mint: NATIVE_MINT }).collect();
+    let mut withdraw = Transaction {
+        chain_id: ChainId::DEVNET,
+        sender,
+        recipient: sender,
+        tx_type: TransactionType::Multi { instructions },
+        nonce: 0,
+        fee: 0,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    withdraw.sign(&key);
+    tx_sender.send(withdraw.clone().into()).await.unwrap();
+    for nonce in 1..5 {
+        tx_sender.send(signed_transfer(&key, Pubkey::new([2; 32]), 1, nonce).into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, withdraw.hash()).await;
+
+    let mut listed = Vec::new();
+    let mut url = format!("{}/v1/withdrawals?from_batch=1&limit=2", base_url);
+    let mut pages = 0;
+    loop {
+        let res = client.get(&url).send().await.unwrap();
+        assert_eq!(res.status(), 200);
+        let page: Value = res.json().await.unwrap();
+        let withdrawals = page["withdrawals"].as_array().unwrap();
+        assert!(withdrawals.len() <= 2);
+        listed.extend(withdrawals.iter().map(|w| (w["batch_id"].as_u64().unwrap(), w["index"].as_u64().unwrap(), w["amount"].as_u64().unwrap())));
+        pages += 1;
+        match page["next"].as_str() {
+            Some(next) => url = format!("{}/v1/withdrawals?cursor={}&limit=2", base_url, next),
+            None => break,
+        }
+    }
+    assert_eq!(pages, 3);
+    assert_eq!(listed, (0..5).map(|i| (1, i, i + 1)).collect::<Vec<_>>());
+
+    let res = client.get(format!("{}/v1/withdrawals?cursor=1-2", base_url)).send().await.unwrap();
+    assert_eq!(res.status(), 400);
+}
+
+#[tokio::test]
+async fn test_token_balances_are_tracked_per_mint() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[10; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([11; 32]);
+    let mint = Pubkey::new([0xee; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+    seed_account(&storage, AccountKey::new(sender, mint), 50);
+
+    let mut last = None;
+    for nonce in 0..5 {
+        let mut tx = Transaction {
+            chain_id: ChainId::DEVNET,
+            sender,
+            recipient,
+            tx_type: TransactionType::Transfer { amount: 10, mint },
+            nonce,
+            fee: 0,
+            valid_until_batch: None,
+            signature: Signature([0; 64]),
+        };
+        tx.sign(&key);
+        last = Some(tx.hash());
+        tx_sender.send(tx.into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, last.unwrap()).await;
+
+    let res = client.get(format!("{}/v1/accounts/{}", base_url, hex::encode(sender.0))).send().await.unwrap();
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["nonce"], 5);
+    assert_eq!(body["balances"].as_array().unwrap().len(), 2);
+    assert_eq!(body["balances"][0]["mint"], hex::encode(NATIVE_MINT.0));
+    assert_eq!(body["balances"][0]["balance"], 1000);
+    assert_eq!(body["balances"][1]["mint"], hex::encode(mint.0));
+    assert_eq!(body["balances"][1]["balance"], 0);
+
+    let res = client.get(format!("{}/v1/accounts/{}", base_url, hex::encode(recipient.0))).send().await.unwrap();
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["balances"].as_array().unwrap().len(), 1);
+    assert_eq!(body["balances"][0]["mint"], hex::encode(mint.0));
+    assert_eq!(body["balances"][0]["balance"], 50);
+}
+
+#[tokio::test]
+async fn test_receipts_record_outcome_of_each_transaction() {
+    let collector = Pubkey::new([13; 32]);
+    let (addr, tx_sender, storage) = spawn_app_with_fees(FeeConfig { collector, min_fee: 1 }).await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[12; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([14; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    let ok = signed_transfer_with_fee(&key, recipient, 100, 0, 1);
+    let overdraw = signed_transfer_with_fee(&key, recipient, 5000, 1, 1);
+    let gap = signed_transfer_with_fee(&key, recipient, 1, 9, 1);
+    let txs = [ok.clone(), overdraw.clone(), gap.clone(), signed_transfer_with_fee(&key, recipient, 1, 2, 1), signed_transfer_with_fee(&key, recipient, 1, 3, 1)];
+    for tx in &txs {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+
+    let stored = wait_for_receipt(&client, &base_url, ok.hash()).await;
+    assert_eq!(stored["receipt"]["status"], "success");
+    assert_eq!(stored["receipt"]["batch_id"], 1);
+    assert_eq!(stored["receipt"]["error_code"], Value::Null);
+    let changes = stored["receipt"]["balance_changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 3);
+    let sender_change = changes.iter().find(|c| c["owner"] == hex::encode(sender.0)).unwrap();
+    assert_eq!((sender_change["before"].as_u64(), sender_change["after"].as_u64()), (Some(1000), Some(899)));
+    let recipient_change = changes.iter().find(|c| c["owner"] == hex::encode(recipient.0)).unwrap();
+    assert_eq!((recipient_change["before"].as_u64(), recipient_change["after"].as_u64()), (Some(0), Some(100)));
+
+    let stored = wait_for_receipt(&client, &base_url, overdraw.hash()).await;
+    assert_eq!(stored["receipt"]["status"], "failed");
+    assert_eq!(stored["receipt"]["error_code"], "insufficient_funds");
+    assert_eq!(stored["receipt"]["index"], 1);
+    let changes = stored["receipt"]["balance_changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 2, "only the fee moves");
+
+    // Past a nonce gap, the transaction waits in the mempool for the gap to fill.
+    let res = client.get(format!("{}/v1/tx/{}", base_url, hex::encode(gap.hash().0))).send().await.unwrap();
+    assert_eq!(res.status(), 404);
+
+    let header: Value = client.get(format!("{}/v1/batches/1", base_url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(header["tx_count"], 4);
+}
+
+#[tokio::test]
+async fn test_deposit_overflow_reports_typed_error() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let recipient = Pubkey::new([15; 32]);
+    seed_account(&storage, AccountKey::native(recipient), u64::MAX - 10);
+
+    let result = submit_deposit(&app_state(&storage, tx_sender), recipient, 11, NATIVE_MINT, 0).await;
+    assert!(matches!(result, Err(ApiError::Execution(ExecutionError::Overflow))));
+
+    let account = fetch_native_account(&client, &base_url, recipient).await;
+    assert_eq!(account.balance, u64::MAX - 10);
+}
+
+#[tokio::test]
+async fn test_overflowing_transfer_fails_and_keeps_only_the_fee() {
+    let collector = Pubkey::new([17; 32]);
+    let (addr, tx_sender, storage) = spawn_app_with_fees(FeeConfig { collector, min_fee: 1 }).await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[16; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let whale = Pubkey::new([18; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+    seed_account(&storage, AccountKey::native(whale), u64::MAX - 5);
+
+    let overflow = signed_transfer_with_fee(&key, whale, 10, 0, 1);
+    tx_sender.send(overflow.clone().into()).await.unwrap();
+    for nonce in 1..5 {
+        tx_sender.send(signed_transfer_with_fee(&key, Pubkey::new([19; 32]), 1, nonce, 1).into()).await.unwrap();
+    }
+
+    let stored = wait_for_receipt(&client, &base_url, overflow.hash()).await;
+    assert_eq!(stored["receipt"]["status"], "failed");
+    assert_eq!(stored["receipt"]["error_code"], "overflow");
+
+    let account = fetch_native_account(&client, &base_url, sender).await;
+    assert_eq!(account.balance, 1000 - 5 - 4);
+    assert_eq!(account.nonce, 5);
+    assert_eq!(fetch_native_account(&client, &base_url, whale).await.balance, u64::MAX - 5);
+    assert_eq!(fetch_native_account(&client, &base_url, collector).await.balance, 5);
+}
+
+#[tokio::test]
+async fn test_multi_instruction_transactions_are_all_or_nothing() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[20; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    seed_account(&storage, AccountKey::native(sender), 1000);
+    let payees: Vec<Pubkey> = (21..24).map(|b| Pubkey::new([b; 32])).collect();
+
+    let multi = |instructions: Vec<Instruction>, nonce: u64| {
+        let mut tx = Transaction {
+            chain_id: ChainId::DEVNET,
+            sender,
+            recipient: sender,
+            tx_type: TransactionType::Multi { instructions },
+            nonce,
+            fee: 0,
+            valid_until_batch: None,
+            signature: Signature([0; 64]),
+        };
+        tx.sign(&key);
+        tx
+    };
+    let pay = |recipient: Pubkey, amount: u64| Instruction::Transfer { recipient, amount, mint: NATIVE_MINT };
+
+    let payroll = multi(payees.iter().map(|&payee| pay(payee, 100)).collect(), 0);
+    let overdrawn = multi(vec![pay(payees[0], 100), pay(payees[1], 5000), pay(payees[2], 100)], 1);
+    for tx in [payroll.clone(), overdrawn.clone()] {
+        tx_sender.send(tx.into()).await.unwrap();
+    }
+    for nonce in 2..5 {
+        tx_sender.send(signed_transfer(&key, sender, 1, nonce).into()).await.unwrap();
+    }
+
+    let stored = wait_for_receipt(&client, &base_url, overdrawn.hash()).await;
+    assert_eq!(stored["receipt"]["status"], "failed");
+    assert_eq!(stored["receipt"]["error_code"], "insufficient_funds");
+    assert_eq!(stored["receipt"]["balance_changes"].as_array().unwrap().len(), 0);
+
+    assert_eq!(fetch_native_account(&client, &base_url, sender).await.balance, 700);
+    for &payee in &payees {
+        assert_eq!(fetch_native_account(&client, &base_url, payee).await.balance, 100);
+    }
+
+    let res = submit(&client, &base_url, &multi(Vec::new(), 5)).await;
+    assert_eq!(res.status(), 422);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "invalid_instruction_count");
+}
+
+#[tokio::test]
+async fn test_expired_transactions_are_rejected() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[25; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([26; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    let with_expiry = |nonce: u64, valid_until_batch: u64| {
+        let mut tx = signed_transfer(&key, recipient, 10, nonce);
+        tx.valid_until_batch = Some(valid_until_batch);
+        tx.sign(&key);
+        tx
+    };
+
+    // Batch 1 is the first sealed, so a transaction valid only until batch 0 is stale.
+    let stale = with_expiry(4, 0);
+    for nonce in 0..4 {
+        tx_sender.send(with_expiry(nonce, 1).into()).await.unwrap();
+    }
+    tx_sender.send(stale.clone().into()).await.unwrap();
+
+    let stored = wait_for_receipt(&client, &base_url, stale.hash()).await;
+    assert_eq!(stored["receipt"]["status"], "rejected");
+    assert_eq!(stored["receipt"]["error_code"], "expired");
+    let account = fetch_native_account(&client, &base_url, sender).await;
+    assert_eq!((account.balance, account.nonce), (960, 4));
+
+    let res = submit(&client, &base_url, &with_expiry(4, 1)).await;
+    assert_eq!(res.status(), 422);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "expired");
+}
+
+#[tokio::test]
+async fn test_public_system_deposits_are_rejected() {
+    let (addr, _tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+    let recipient = Pubkey::new([66; 32]);
+
+    let deposit = Transaction {
+        chain_id: ChainId::DEVNET,
+        sender: SYSTEM_SENDER,
+        recipient,
+        tx_type: TransactionType::Deposit { amount: 1_000_000, mint: NATIVE_MINT },
+        nonce: 0,
+        fee: 0,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    let res = submit(&client, &base_url, &deposit).await;
+    assert_eq!(res.status(), 400);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "bad_request");
+
+    // Nothing was queued, so nothing can be minted.
+    assert_eq!(storage.rocksdb.iterator_cf(storage.cf_pending_txs(), rocksdb::IteratorMode::Start).count(), 0);
+    assert_eq!(client.get(format!("{}/v1/accounts/{}", base_url, hex::encode(recipient.0))).send().await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn test_user_signed_deposits_are_rejected() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[60; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    let mut deposit = signed_transfer(&key, sender, 0, 4);
+    deposit.tx_type = TransactionType::Deposit { amount: 500, mint: NATIVE_MINT };
+    deposit.sign(&key);
+    for nonce in 0..4 {
+        tx_sender.send(signed_transfer(&key, Pubkey::new([61; 32]), 10, nonce).into()).await.unwrap();
+    }
+    tx_sender.send(deposit.clone().into()).await.unwrap();
+
+    let stored = wait_for_receipt(&client, &base_url, deposit.hash()).await;
+    assert_eq!(stored["receipt"]["status"], "rejected");
+    assert_eq!(stored["receipt"]["error_code"], "unauthorized_deposit");
+    let account = fetch_native_account(&client, &base_url, sender).await;
+    assert_eq!((account.balance, account.nonce), (960, 4));
+
+    let res = submit(&client, &base_url, &deposit).await;
+    assert_eq!(res.status(), 400);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "bad_request");
+    assert_eq!(fetch_native_account(&client, &base_url, sender).await.balance, 960);
+}
+
+#[tokio::test]
+async fn test_chain_id_is_checked_against_the_bridge_config_account() {
+    // Built to the layout `BridgeConfig` documents, not captured from L1.
+    let fixture: Value = serde_json::from_str(include_str!("fixtures/synthetic_bridge_config_account.json")).unwrap();
+    let config = BridgeConfig::from_account_info(&fixture).unwrap();
+    assert_eq!(config.sequencer_authority, core::array::from_fn(|i| 0x20 + i as u8));
+    assert_eq!(config.domain, ChainId::DEVNET.0);
+
+    // Any other layout fails closed.
+    assert!(BridgeConfig::from_account_data(&[0; BridgeConfig::LEN - 1]).is_err());
+    assert!(BridgeConfig::from_account_data(&[0; BridgeConfig::LEN + 8]).is_err());
+    let mut foreign = fixture.clone();
+    foreign["result"]["value"]["owner"] = json!("11111111111111111111111111111111");
+    assert!(BridgeConfig::from_account_info(&foreign).unwrap_err().contains("not the bridge program"));
+
+    // A JSON-RPC endpoint serving the fixture as the config account.
+    let l1 = axum::Router::new().route("/", axum::routing::post(move || async move { axum::Json(fixture) }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let rpc_url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(serve(listener, l1).into_future());
+
+    // `bon`'s init_config uses the devnet chain ID as the domain.
+    check_chain_id(&rpc_url, "config", &ChainId::DEVNET.0).await.unwrap();
+    let mismatch = check_chain_id(&rpc_url, "config", &[2; 32]).await.unwrap_err();
+    assert!(mismatch.to_string().contains("does not match bridge domain"));
+}
+
+#[tokio::test]
+async fn test_transactions_for_another_chain_are_rejected() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let chain: Value = client.get(format!("{}/v1/chain", base_url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(chain["chain_id"], hex::encode(ChainId::DEVNET.0));
+
+    let key = SigningKey::from_bytes(&[27; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([28; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    let mut foreign = signed_transfer(&key, recipient, 10, 4);
+    foreign.chain_id = ChainId([2; 32]);
+    foreign.sign(&key);
+
+    let res = submit(&client, &base_url, &foreign).await;
+    assert_eq!(res.status(), 422);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "wrong_chain");
+
+    // The sequencer enforces it too, for transactions that bypass the RPC checks.
+    for nonce in 0..4 {
+        tx_sender.send(signed_transfer(&key, recipient, 10, nonce).into()).await.unwrap();
+    }
+    tx_sender.send(foreign.clone().into()).await.unwrap();
+
+    let stored = wait_for_receipt(&client, &base_url, foreign.hash()).await;
+    assert_eq!(stored["receipt"]["status"], "rejected");
+    assert_eq!(stored["receipt"]["error_code"], "wrong_chain");
+    assert_eq!(fetch_native_account(&client, &base_url, sender).await.balance, 960);
+}
+
+#[test]
+fn test_state_commitment_test_vectors() {
+    // circomlib's reference: poseidon([1, 2]) =
+    // 7853200120776062878684798364095072458815029376092732009249414926327459813530.
+    let field = |n: u8| core::array::from_fn(|i| if i == 31 { n } else { 0 });
+    assert_eq!(hex::encode(poseidon2(field(1), field(2))), "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a");
+
+    let mut accounts = BTreeMap::new();
+    assert_eq!(
+        hex::encode(compute_state_commitment(&accounts, 0)),
+        "28e9c9aa46be44d58018c70fe44df00067e67328035620a6d178c51c62728ce1"
+    );
+
+    accounts.insert(AccountKey::native(Pubkey([1; 32])), Account { balance: 1000, nonce: 0 });
+    assert_eq!(
+        hex::encode(compute_state_commitment(&accounts, 1)),
+        "11faad1ae630956af366c2b738ce03781eb4c8b1d8fb8a3c914c3cb0bbd43f1d"
+    );
+
+    accounts.insert(AccountKey::new(Pubkey([2; 32]), Pubkey([3; 32])), Account { balance: 500, nonce: 7 });
+    accounts.insert(AccountKey::native(Pubkey([0xff; 32])), Account { balance: u64::MAX, nonce: u64::MAX });
+    let root = compute_state_commitment(&accounts, 42);
+    assert_eq!(hex::encode(root), "20dfd1b66fdc92960c120fa009856ad6ce342b394e16b2b2d0397453fc120958");
+
+    // Balances are bound to their accounts, so swapping two changes the root.
+    accounts.get_mut(&AccountKey::native(Pubkey([1; 32]))).unwrap().balance = 500;
+    accounts.get_mut(&AccountKey::new(Pubkey([2; 32]), Pubkey([3; 32]))).unwrap().balance = 1000;
+    assert_ne!(compute_state_commitment(&accounts, 42), root);
+}
+
+#[tokio::test]
+async fn test_parallel_state_commitment_matches_sequential() {
+    let storage = temp_storage().await;
+    // Sizes on both sides of the subtree size built on a single thread.
+    for count in [0u64, 1, 2, 3, 100, 1500] {
+        let accounts: BTreeMap<_, _> = (0..count)
+            .map(|i| {
+                let mut owner = [0u8; 32];
+                owner[..8].copy_from_slice(&i.wrapping_mul(0x9e37_79b9_7f4a_7c15).to_be_bytes());
+                let mint = if i % 3 == 0 { Pubkey::new([7; 32]) } else { NATIVE_MINT };
+                (AccountKey::new(Pubkey::new(owner), mint), Account { balance: i * 1000 + 1, nonce: i % 5 })
+            })
+            .collect();
+
+        assert_eq!(compute_state_commitment_parallel(&accounts, count), compute_state_commitment(&accounts, count), "{} accounts", count);
+
+        let mut sequential = StateTree::new(&storage).update(&EMPTY_ROOT, &accounts).unwrap();
+        let mut parallel = StateTree::from_accounts(&accounts);
+        assert_eq!(parallel.root, sequential.root, "{} accounts", count);
+        sequential.nodes.sort_by_key(|(hash, _)| *hash);
+        parallel.nodes.sort_by_key(|(hash, _)| *hash);
+        assert!(parallel.nodes == sequential.nodes, "{} accounts", count);
+    }
+}
+
+#[tokio::test]
+async fn test_state_tree_proves_accounts_at_each_batch() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([2; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    let mut roots = Vec::new();
+    for batch in 0..2u64 {
+        let txs: Vec<Transaction> = (0..5).map(|nonce| signed_transfer(&key, recipient, 10, batch * 5 + nonce)).collect();
+        for tx in &txs {
+            tx_sender.send(tx.clone().into()).await.unwrap();
+        }
+        wait_for_receipt(&client, &base_url, txs[4].hash()).await;
+        roots.push(fetch_state_root(&client, &base_url, batch + 1).await);
+    }
+    assert_ne!(roots[0], roots[1]);
+
+    let mut tree = StateTree::new(&storage);
+    let sender_key = AccountKey::native(sender);
+
+    // Older roots stay provable after later batches.
+    for (root, balance, nonce) in [(roots[0], 950, 5), (roots[1], 900, 10)] {
+        let (account, proof) = tree.prove(&root, &sender_key).unwrap();
+        assert_eq!(account, Some(Account { balance, nonce }));
+        assert!(proof.verify(&root, &sender_key, account.as_ref()));
+        assert!(!proof.verify(&root, &sender_key, Some(&Account { balance: balance + 1, nonce })));
+        assert!(!proof.verify(&root, &sender_key, None));
+    }
+    let (account, proof) = tree.prove(&roots[1], &AccountKey::native(recipient)).unwrap();
+    assert_eq!(account, Some(Account { balance: 100, nonce: 0 }));
+    assert!(proof.verify(&roots[1], &AccountKey::native(recipient), account.as_ref()));
+
+    // Absent keys: another owner, and the sender under a mint it never held.
+    for absent in [AccountKey::native(Pubkey::new([9; 32])), AccountKey::new(sender, Pubkey::new([7; 32]))] {
+        let (account, proof) = tree.prove(&roots[1], &absent).unwrap();
+        assert_eq!(account, None);
+        assert!(proof.verify(&roots[1], &absent, None));
+        assert!(!proof.verify(&roots[1], &absent, Some(&Account::default())));
+    }
+}
+
+/// Reads the state root committed by batch `batch_id` from `/v1/batches`.
+async fn fetch_state_root(client: &reqwest::Client, base_url: &str, batch_id: u64) -> [u8; 32] {
+    let header: Value = client.get(format!("{}/v1/batches/{}", base_url, batch_id)).send().await.unwrap().json().await.unwrap();
+    hex::decode(header["new_root"].as_str().unwrap()).unwrap().try_into().unwrap()
+}
+
+#[tokio::test]
+async fn test_system_deposits_are_sequenced_into_the_state_root() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+    let recipient = Pubkey::new([21; 32]);
+    let state = app_state(&storage, tx_sender);
+
+    let mut deposit_hashes = Vec::new();
+    // The repeated nonce 0 is the same deposit again and must not mint twice.
+    // The mempool turns it away, so the other four seal on the timeout.
+    for nonce in [0, 1, 2, 0, 3] {
+        match submit_deposit(&state, recipient, 100, NATIVE_MINT, nonce).await {
+            Ok(tx_hash) => deposit_hashes.push(tx_hash),
+            Err(e) => assert!(deposit_hashes.len() == 3 && matches!(e, ApiError::Mempool(MempoolError::Duplicate))),
+        }
+    }
+    assert_eq!(deposit_hashes.len(), 4);
+    let stored = wait_for_receipt(&client, &base_url, deposit_hashes[0]).await;
+    assert_eq!(stored["receipt"]["status"], "success");
+
+    let account = fetch_native_account(&client, &base_url, recipient).await;
+    assert_eq!(account.balance, 400);
+
+    let root = fetch_state_root(&client, &base_url, 1).await;
+    let key = AccountKey::native(recipient);
+    let (proven, proof) = StateTree::new(&storage).prove(&root, &key).unwrap();
+    assert_eq!(proven, Some(account));
+    assert!(proof.verify(&root, &key, proven.as_ref()));
+}
+
+#[tokio::test]
+async fn test_system_deposit_replays_are_ignored() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+    let recipient = Pubkey::new([62; 32]);
+    let state = app_state(&storage, tx_sender);
+    let deposit = |recipient: Pubkey, amount: u64, nonce: u64| submit_deposit(&state, recipient, amount, NATIVE_MINT, nonce);
+
+    // The same recipient, amount, mint and nonce is a replay, whether it is
+    // still queued or already sealed. A different amount is another deposit.
+    deposit(recipient, 100, 7).await.unwrap();
+    assert!(matches!(deposit(recipient, 100, 7).await, Err(ApiError::Mempool(MempoolError::Duplicate))));
+    let other = deposit(recipient, 50, 7).await.unwrap();
+    wait_for_receipt(&client, &base_url, other).await;
+    assert_eq!(fetch_native_account(&client, &base_url, recipient).await.balance, 150);
+
+    // The replay shares the first deposit's hash, so wait on a deposit queued after it.
+    deposit(recipient, 100, 7).await.unwrap();
+    let marker = deposit(Pubkey::new([63; 32]), 1, 0).await.unwrap();
+    wait_for_receipt(&client, &base_url, marker).await;
+    assert_eq!(fetch_native_account(&client, &base_url, recipient).await.balance, 150);
+}
+
+#[tokio::test]
+async fn test_accounts_present_at_startup_are_committed() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let bystander = AccountKey::native(Pubkey::new([22; 32]));
+    seed_account(&storage, AccountKey::native(sender), 1000);
+    seed_account(&storage, bystander, 500);
+
+    let (addr, tx_sender, storage) = spawn_app_on(storage, test_fees(), CommitmentScheme::SparseMerkle).await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+    let txs: Vec<Transaction> = (0..5).map(|nonce| signed_transfer(&key, Pubkey::new([2; 32]), 10, nonce)).collect();
+    for tx in &txs {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, txs[4].hash()).await;
+
+    // Batch 1 never touched the bystander, but the tree built at startup holds it.
+    let root = fetch_state_root(&client, &base_url, 1).await;
+    let (account, proof) = StateTree::new(&storage).prove(&root, &bystander).unwrap();
+    assert_eq!(account, Some(Account { balance: 500, nonce: 0 }));
+    assert!(proof.verify(&root, &bystander, account.as_ref()));
+
+    let stored = storage.rocksdb.get_cf(storage.cf_batches(), 1u64.to_be_bytes()).unwrap().unwrap();
+    let header = BlockHeader::from_bytes(&stored).unwrap();
+    assert_eq!(header.new_root, root);
+}
+
+/// Rebuilds the `AccountProof` served by `/v1/accounts/{pubkey}/proof`.
+fn decode_account_proof(body: &Value) -> AccountProof {
+    let pubkey = |value: &Value| Pubkey::new(hex::decode(value.as_str().unwrap()).unwrap().try_into().unwrap());
+    let account = |value: &Value| Account { balance: value["balance"].as_u64().unwrap(), nonce: value["nonce"].as_u64().unwrap() };
+    let other_leaf = &body["other_leaf"];
+    AccountProof {
+        header: serde_json::from_value(body["header"].clone()).unwrap(),
+        key: AccountKey::new(pubkey(&body["owner"]), pubkey(&body["mint"])),
+        account: (!body["account"].is_null()).then(|| account(&body["account"])),
+        proof: MerkleProof {
+            siblings: body["siblings"].as_array().unwrap().iter()
+                .map(|sibling| hex::decode(sibling.as_str().unwrap()).unwrap().try_into().unwrap())
+                .collect(),
+            other_leaf: (!other_leaf.is_null()).then(|| (AccountKey::new(pubkey(&other_leaf["owner"]), pubkey(&other_leaf["mint"])), account(other_leaf))),
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_account_proof_endpoint() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    seed_account(&storage, AccountKey::native(sender), 1000);
+    for nonce in 0..10 {
+        let tx = signed_transfer(&key, Pubkey::new([2; 32]), 10, nonce);
+        tx_sender.send(tx.clone().into()).await.unwrap();
+        if nonce % 5 == 4 {
+            wait_for_receipt(&client, &base_url, tx.hash()).await;
+        }
+    }
+
+    let fetch_proof = |query: String| {
+        let client = client.clone();
+        let url = format!("{}/v1/accounts/{}/proof{}", base_url, hex::encode(sender.0), query);
+        async move { client.get(url).send().await.unwrap() }
+    };
+
+    // Defaults to the native balance at the tip.
+    let res = fetch_proof(String::new()).await;
+    assert_eq!(res.status(), 200);
+    let at_tip = decode_account_proof(&res.json().await.unwrap());
+    assert_eq!(at_tip.header.batch_id, 2);
+    assert_eq!(at_tip.account, Some(Account { balance: 900, nonce: 10 }));
+    assert!(at_tip.verify());
+
+    let mut at_first = decode_account_proof(&fetch_proof("?batch=1".into()).await.json().await.unwrap());
+    assert_eq!(at_first.header.batch_id, 1);
+    assert_eq!(at_first.account, Some(Account { balance: 950, nonce: 5 }));
+    assert!(at_first.verify());
+
+    // A node cannot pass off another balance or another batch's root.
+    at_first.account = Some(Account { balance: 1000, nonce: 5 });
+    assert!(!at_first.verify());
+    at_first.account = Some(Account { balance: 950, nonce: 5 });
+    at_first.header.new_root = at_tip.header.new_root;
+    assert!(!at_first.verify());
+
+    let res = fetch_proof(format!("?mint={}", hex::encode([7u8; 32]))).await;
+    assert_eq!(res.status(), 200);
+    let absent = decode_account_proof(&res.json().await.unwrap());
+    assert_eq!(absent.account, None);
+    assert!(absent.verify());
+
+    assert_eq!(fetch_proof("?batch=99".into()).await.status(), 404);
+    assert_eq!(fetch_proof("?mint=zz".into()).await.status(), 400);
+}
+
+#[tokio::test]
+async fn test_commitment_scheme_is_chosen_per_chain_and_recorded_in_headers() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let sender = AccountKey::native(Pubkey::new(key.verifying_key().to_bytes()));
+    seed_account(&storage, sender, 1000);
+
+    let (addr, tx_sender, storage) = spawn_app_on(storage, test_fees(), CommitmentScheme::AccountsFold).await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+    let txs: Vec<Transaction> = (0..5).map(|nonce| signed_transfer(&key, Pubkey::new([2; 32]), 10, nonce)).collect();
+    for tx in &txs {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, txs[4].hash()).await;
+
+    let stored = storage.rocksdb.get_cf(storage.cf_batches(), 1u64.to_be_bytes()).unwrap().unwrap();
+    let header = BlockHeader::from_bytes(&stored).unwrap();
+    assert_eq!(CommitmentScheme::from_flags(header.flags), Some(CommitmentScheme::AccountsFold));
+    let accounts = BTreeMap::from([
+        (sender, Account { balance: 950, nonce: 5 }),
+        (AccountKey::native(Pubkey::new([2; 32])), Account { balance: 50, nonce: 0 }),
+    ]);
+    assert_eq!(header.new_root, compute_state_commitment(&accounts, 1));
+
+    let (account, proof) = AccountsFold.prove(&storage, &header, &sender).unwrap().unwrap();
+    assert_eq!(account, Some(Account { balance: 950, nonce: 5 }));
+    assert!(proof.verify(&header, &sender, account.as_ref()));
+    assert!(!proof.verify(&header, &sender, Some(&Account { balance: 1000, nonce: 5 })));
+
+    // The header's flags say which scheme a proof must come from.
+    let as_tree = BlockHeader { flags: CommitmentScheme::SparseMerkle.to_flags(), ..header.clone() };
+    assert!(!proof.verify(&as_tree, &sender, account.as_ref()));
+    assert!(!StateProof::Merkle(MerkleProof { siblings: Vec::new(), other_leaf: None }).verify(&header, &sender, None));
+
+    // Merkle proofs are only served for state tree roots.
+    let res = client.get(format!("{}/v1/accounts/{}/proof", base_url, hex::encode(sender.owner.0))).send().await.unwrap();
+    assert_eq!(res.status(), 404);
+
+    assert_eq!("fold".parse::<CommitmentScheme>(), Ok(CommitmentScheme::AccountsFold));
+    assert_eq!(CommitmentScheme::from_flags(0xff), None);
+}
+
+#[tokio::test]
+async fn test_a_chain_cannot_switch_commitment_schemes() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[71; 32]);
+    seed_account(&storage, AccountKey::native(Pubkey::new(key.verifying_key().to_bytes())), 1000);
+
+    // Any scheme may start a chain.
+    let (_addr, tx_sender, storage) = spawn_app_on(storage, test_fees(), CommitmentScheme::AccountsFold).await;
+    for nonce in 0..5 {
+        tx_sender.send(signed_transfer(&key, Pubkey::new([72; 32]), 10, nonce).into()).await.unwrap();
+    }
+    wait_for_batch(&storage, 1).await;
+
+    // Restarting it with another scheme fails; the recorded one is the default.
+    let restart = || async {
+        let (_tx_sender, tx_receiver) = mpsc::channel(1);
+        RollupCore::new(Arc::clone(&storage), tx_receiver, test_sequencer_config(), test_fees(), ChainId::DEVNET).await.unwrap()
+    };
+    let result = restart().await.with_commitment(CommitmentScheme::SparseMerkle.build());
+    assert!(matches!(result, Err(SequencerError::InvalidConfig(_))));
+    assert!(restart().await.with_commitment(CommitmentScheme::AccountsFold.build()).is_ok());
+}
+
+#[tokio::test]
+async fn test_zero_sequencer_limits_are_refused() {
+    let storage = temp_storage().await;
+    let zeroed = [
+        SequencerConfig { max_txs_per_batch: 0, ..SequencerConfig::default() },
+        SequencerConfig { max_batch_bytes: 0, ..SequencerConfig::default() },
+        SequencerConfig { max_batch_compute: 0, ..SequencerConfig::default() },
+        SequencerConfig { seal_timeout: Duration::ZERO, ..SequencerConfig::default() },
+        SequencerConfig { max_pending_txs: 0, ..SequencerConfig::default() },
+        SequencerConfig { max_pending_per_sender: 0, ..SequencerConfig::default() },
+    ];
+    for config in zeroed {
+        assert!(matches!(config.validate(), Err(SequencerError::InvalidConfig(_))), "{:?}", config);
+        let (_tx_sender, tx_receiver) = mpsc::channel(1);
+        let result = RollupCore::new(Arc::clone(&storage), tx_receiver, config, test_fees(), ChainId::DEVNET).await;
+        assert!(matches!(result, Err(SequencerError::InvalidConfig(_))));
+    }
+    assert!(SequencerConfig::default().validate().is_ok());
+}
+
+#[tokio::test]
+async fn test_batches_seal_on_size_or_latency() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[1; 32]);
+    seed_account(&storage, AccountKey::native(Pubkey::new(key.verifying_key().to_bytes())), 1000);
+
+    let (tx_sender, tx_receiver) = mpsc::channel(100);
+    let seal_timeout = Duration::from_millis(300);
+    let config = SequencerConfig { max_txs_per_batch: 3, seal_timeout, ..SequencerConfig::default() };
+    let rollup_core = RollupCore::new(Arc::clone(&storage), tx_receiver, config, test_fees(), ChainId::DEVNET).await.unwrap();
+    tokio::spawn(rollup_core.run());
+
+    // Three pending transactions fill a batch without waiting.
+    let started = Instant::now();
+    for nonce in 0..3 {
+        tx_sender.send(signed_transfer(&key, Pubkey::new([2; 32]), 10, nonce).into()).await.unwrap();
+    }
+    assert_eq!(wait_for_batch(&storage, 1).await.tx_count, 3);
+    assert!(started.elapsed() < seal_timeout);
+
+    // A lone transaction waits for the latency limit, then is sealed alone.
+    let started = Instant::now();
+    tx_sender.send(signed_transfer(&key, Pubkey::new([2; 32]), 10, 3).into()).await.unwrap();
+    assert_eq!(wait_for_batch(&storage, 2).await.tx_count, 1);
+    assert!(started.elapsed() >= seal_timeout);
+}
+
+#[tokio::test]
+async fn test_batches_are_cut_at_byte_and_compute_limits() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[1; 32]);
+    seed_account(&storage, AccountKey::native(Pubkey::new(key.verifying_key().to_bytes())), 1000);
+    let transfer_bytes = bincode::serialized_size(&signed_transfer(&key, Pubkey::new([2; 32]), 10, 0)).unwrap() as usize;
+
+    let (tx_sender, tx_receiver) = mpsc::channel(100);
+    let config = SequencerConfig {
+        max_txs_per_batch: 100,
+        max_batch_bytes: 2 * transfer_bytes,
+        max_batch_compute: 3,
+        seal_timeout: Duration::from_millis(300),
+        ..SequencerConfig::default()
+    };
+    let rollup_core = RollupCore::new(Arc::clone(&storage), tx_receiver, config, test_fees(), ChainId::DEVNET).await.unwrap();
+    tokio::spawn(rollup_core.run());
+
+    // Two transfers fill the byte limit; the third waits for the next batch.
+    for nonce in 0..3 {
+        tx_sender.send(signed_transfer(&key, Pubkey::new([2; 32]), 10, nonce).into()).await.unwrap();
+    }
+    assert_eq!(wait_for_batch(&storage, 1).await.tx_count, 2);
+    assert_eq!(wait_for_batch(&storage, 2).await.tx_count, 1);
+
+    // A transaction over the compute limit fits no batch and is dropped.
+    let mut oversized = Transaction {
+        chain_id: ChainId::DEVNET,
+        sender: Pubkey::new(key.verifying_key().to_bytes()),
+        recipient: Pubkey::new([0; 32]),
+        tx_type: TransactionType::Multi { instructions: vec![Instruction::Transfer { recipient: Pubkey::new([2; 32]), amount: 1, mint: NATIVE_MINT }; 4] },
+        nonce: 3,
+        fee: 0,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    oversized.sign(&key);
+    assert_eq!(oversized.compute_units(), 4);
+    tx_sender.send(oversized.into()).await.unwrap();
+
+    // A deposit queued after it is sealed without it.
+    let deposit = Transaction {
+        chain_id: ChainId::DEVNET,
+        sender: SYSTEM_SENDER,
+        recipient: Pubkey::new([2; 32]),
+        tx_type: TransactionType::Deposit { amount: 1, mint: NATIVE_MINT },
+        nonce: 0,
+        fee: 0,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    tx_sender.send(deposit.clone().into()).await.unwrap();
+    assert_eq!(wait_for_batch(&storage, 3).await.tx_root, merkle_root(&[deposit.hash().0]));
+}
+
+#[tokio::test]
+async fn test_configz_reports_sequencer_config() {
+    let (addr, _tx_sender, _storage) = spawn_app().await;
+    let res = reqwest::get(format!("http://{}/configz", addr)).await.unwrap();
+    assert_eq!(res.status(), 200);
+    let config: Value = res.json().await.unwrap();
+    let expected = test_sequencer_config();
+    assert_eq!(config["max_txs_per_batch"], 5);
+    assert_eq!(config["max_batch_bytes"], expected.max_batch_bytes);
+    assert_eq!(config["max_batch_compute"], expected.max_batch_compute);
+    assert_eq!(config["seal_timeout_ms"], expected.seal_timeout.as_millis() as u64);
+}
+
+#[test]
+fn test_mempool_orders_by_nonce_within_senders_and_fee_across_them() {
+    let (alice, bob, carol) = (SigningKey::from_bytes(&[31; 32]), SigningKey::from_bytes(&[32; 32]), SigningKey::from_bytes(&[33; 32]));
+    let owner = |key: &SigningKey| Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([2; 32]);
+    let config = SequencerConfig::default();
+    let mut mempool = Mempool::new(&config);
+
+    // Alice's nonces arrive out of order and her later ones pay more; Bob
+    // pays the most but starts above his committed nonce; Carol's is stale.
+    let a2 = signed_transfer_with_fee(&alice, recipient, 1, 2, 9);
+    let a1 = signed_transfer_with_fee(&alice, recipient, 1, 1, 1);
+    let a3 = signed_transfer_with_fee(&alice, recipient, 1, 3, 1);
+    let b4 = signed_transfer_with_fee(&bob, recipient, 1, 4, 5);
+    let b5 = signed_transfer_with_fee(&bob, recipient, 1, 5, 5);
+    let b7 = signed_transfer_with_fee(&bob, recipient, 1, 7, 50);
+    let c0 = signed_transfer_with_fee(&carol, recipient, 1, 0, 100);
+    for tx in [&a2, &a1, &a3, &b4, &b5, &b7, &c0] {
+        mempool.insert(tx.clone()).unwrap();
+    }
+    assert_eq!(mempool.insert(a2.clone()), Err(MempoolError::Duplicate));
+    assert_eq!(mempool.len(), 7);
+
+    let committed = BTreeMap::from([(owner(&alice), 1), (owner(&bob), 4), (owner(&carol), 1)]);
+    let selected = mempool.select(&config, |sender| Ok(committed[sender])).unwrap();
+    let hashes: Vec<_> = selected.iter().map(|tx| tx.hash()).collect();
+    assert_eq!(hashes, [b4.hash(), b5.hash(), a1.hash(), a2.hash(), a3.hash()]);
+
+    // Bob's nonce 7 waits for 6; Carol's nonce 0 was dropped.
+    assert_eq!(mempool.len(), 1);
+    assert!(mempool.contains(&b7.hash()));
+    assert!(mempool.select(&config, |sender| Ok(committed[sender] + 2)).unwrap().is_empty());
+
+    // A transaction for a pending nonce only replaces it by paying more.
+    let b6 = signed_transfer_with_fee(&bob, recipient, 1, 6, 5);
+    mempool.insert(b6.clone()).unwrap();
+    assert_eq!(mempool.insert(signed_transfer_with_fee(&bob, recipient, 2, 6, 5)), Err(MempoolError::Underpriced));
+    let b6_bumped = signed_transfer_with_fee(&bob, recipient, 2, 6, 6);
+    mempool.insert(b6_bumped.clone()).unwrap();
+    assert!(!mempool.contains(&b6.hash()));
+    let selected = mempool.select(&config, |_| Ok(6)).unwrap();
+    assert_eq!(selected.iter().map(|tx| tx.hash()).collect::<Vec<_>>(), [b6_bumped.hash(), b7.hash()]);
+    assert!(mempool.is_empty());
+}
+
+#[test]
+fn test_mempool_caps_evict_the_lowest_priority_transactions() {
+    let (alice, bob, carol) = (SigningKey::from_bytes(&[34; 32]), SigningKey::from_bytes(&[35; 32]), SigningKey::from_bytes(&[36; 32]));
+    let recipient = Pubkey::new([2; 32]);
+    let config = SequencerConfig { max_pending_txs: 3, max_pending_per_sender: 2, ..SequencerConfig::default() };
+    let mut mempool = Mempool::new(&config);
+
+    // Per sender: a lower nonce displaces the highest, a higher one is refused.
+    let a0 = signed_transfer_with_fee(&alice, recipient, 1, 0, 1);
+    let a2 = signed_transfer_with_fee(&alice, recipient, 1, 2, 1);
+    mempool.insert(a0.clone()).unwrap();
+    mempool.insert(a2.clone()).unwrap();
+    assert_eq!(mempool.insert(signed_transfer_with_fee(&alice, recipient, 1, 3, 1)), Err(MempoolError::SenderFull));
+    let a1 = signed_transfer_with_fee(&alice, recipient, 1, 1, 1);
+    mempool.insert(a1.clone()).unwrap();
+    assert!(!mempool.contains(&a2.hash()));
+
+    // Globally: a higher fee evicts the lowest-fee tail, an equal fee is refused.
+    let b0 = signed_transfer_with_fee(&bob, recipient, 1, 0, 3);
+    mempool.insert(b0.clone()).unwrap();
+    assert_eq!(mempool.len(), 3);
+    assert_eq!(mempool.insert(signed_transfer_with_fee(&carol, recipient, 1, 0, 1)), Err(MempoolError::Full));
+    let c0 = signed_transfer_with_fee(&carol, recipient, 1, 0, 2);
+    mempool.insert(c0.clone()).unwrap();
+    assert_eq!(mempool.len(), 3);
+    assert!(!mempool.contains(&a1.hash()));
+    for tx in [&a0, &b0, &c0] {
+        assert!(mempool.contains(&tx.hash()));
+    }
+
+    // Deposits skip the nonce queue and are never evicted.
+    let deposit = |amount| Transaction {
+        chain_id: ChainId::DEVNET,
+        sender: SYSTEM_SENDER,
+        recipient,
+        tx_type: TransactionType::Deposit { amount, mint: NATIVE_MINT },
+        nonce: 0,
+        fee: 0,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    for amount in 1..=3 {
+        mempool.insert(deposit(amount)).unwrap();
+    }
+    assert_eq!(mempool.insert(deposit(4)), Err(MempoolError::Full));
+    let selected = mempool.select(&config, |_| Ok(0)).unwrap();
+    assert_eq!(selected.iter().map(|tx| tx.hash()).collect::<Vec<_>>(), [deposit(1).hash(), deposit(2).hash(), deposit(3).hash()]);
+}
+
+#[tokio::test]
+async fn test_mempool_rejections_are_reported_to_the_client() {
+    let (addr, _tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[63; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([64; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    // Nonce 1 waits on the gap at nonce 0, so it stays pending.
+    let res = submit(&client, &base_url, &signed_transfer_with_fee(&key, recipient, 10, 1, 5)).await;
+    assert_eq!(res.status(), 200);
+
+    let res = submit(&client, &base_url, &signed_transfer_with_fee(&key, recipient, 10, 1, 3)).await;
+    assert_eq!(res.status(), 409);
+    let error: Value = res.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "underpriced");
+    let journaled = storage.rocksdb.iterator_cf(storage.cf_pending_txs(), rocksdb::IteratorMode::Start).count();
+    assert_eq!(journaled, 1);
+}
+
+#[tokio::test]
+async fn test_queued_transactions_survive_a_sequencer_restart() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[37; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    let recipient = Pubkey::new([38; 32]);
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    // A sequencer that admits the transactions but stops before sealing them.
+    let (tx_sender, tx_receiver) = mpsc::channel(100);
+    let config = SequencerConfig { seal_timeout: Duration::from_secs(60), ..SequencerConfig::default() };
+    let rollup_core = RollupCore::new(Arc::clone(&storage), tx_receiver, config, test_fees(), ChainId::DEVNET).await.unwrap();
+    let core = tokio::spawn(rollup_core.run());
+    let rpc_state = app_state(&storage, tx_sender);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(serve(listener, create_router(rpc_state)).into_future());
+
+    let client = reqwest::Client::new();
+    let txs: Vec<Transaction> = (0..2).map(|nonce| signed_transfer(&key, recipient, 10, nonce)).collect();
+    for tx in &txs {
+        let res = submit(&client, &base_url, tx).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.json::<Value>().await.unwrap()["status"], "queued");
+    }
+    server.abort();
+    core.abort();
+    let journaled = || storage.rocksdb.iterator_cf(storage.cf_pending_txs(), rocksdb::IteratorMode::Start).count();
+    assert_eq!(journaled(), 2);
+
+    // A new sequencer on the same storage restores and seals them.
+    let (addr, _tx_sender, _) = spawn_app_on(storage.clone(), test_fees(), CommitmentScheme::default()).await;
+    let base_url = format!("http://{}", addr);
+    wait_for_receipt(&client, &base_url, txs[1].hash()).await;
+    let header = BlockHeader::from_bytes(&storage.rocksdb.get_cf(storage.cf_batches(), 1u64.to_be_bytes()).unwrap().unwrap()).unwrap();
+    assert_eq!(header.tx_root, merkle_root(&txs.iter().map(|tx| tx.hash().0).collect::<Vec<_>>()));
+    let account = fetch_native_account(&client, &base_url, sender).await;
+    assert_eq!(account, Account { balance: 980, nonce: 2 });
+    assert_eq!(journaled(), 0);
+}
+
+#[tokio::test]
+async fn test_a_failed_commit_does_not_reuse_its_batch_id() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[67; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    // RocksDB takes each batch, then the SQLite half of `commit_batch` fails.
+    sqlx::query("CREATE TRIGGER fail_commit BEFORE INSERT ON batches BEGIN SELECT RAISE(ABORT, 'injected'); END")
+        .execute(&storage.sqlite).await.unwrap();
+    let (addr, tx_sender, storage) = spawn_app_on(storage, test_fees(), CommitmentScheme::default()).await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+    let txs: Vec<Transaction> = (0..10).map(|nonce| signed_transfer(&key, Pubkey::new([68; 32]), 10, nonce)).collect();
+    for tx in &txs[..5] {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, txs[4].hash()).await;
+
+    // The next batch follows batch 1 instead of overwriting it.
+    for tx in &txs[5..] {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, txs[9].hash()).await;
+    let header = |batch_id: u64| BlockHeader::from_bytes(&storage.rocksdb.get_cf(storage.cf_batches(), batch_id.to_be_bytes()).unwrap().unwrap()).unwrap();
+    assert_eq!(header(1).tx_root, merkle_root(&txs[..5].iter().map(|tx| tx.hash().0).collect::<Vec<_>>()));
+    assert_eq!(header(2).prev_root, header(1).new_root);
+    let account = fetch_native_account(&client, &base_url, sender).await;
+    assert_eq!(account, Account { balance: 900, nonce: 10 });
+}
+
+/// Fails the next `failures` updates.
+struct FailingCommitment {
+    inner: Box<dyn StateCommitment>,
+    failures: Arc<AtomicUsize>,
+}
+
+impl StateCommitment for FailingCommitment {
+    fn scheme(&self) -> CommitmentScheme { self.inner.scheme() }
+
+    fn root(&mut self, storage: &Storage, tip: &BlockHeader) -> Result<[u8; 32], DbError> {
+        self.inner.root(storage, tip)
+    }
+
+    fn update(&mut self, storage: &Storage, tip: &BlockHeader, changes: &HashMap<AccountKey, Account>) -> Result<CommitmentUpdate, DbError> {
+        if self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+            return Err(DbError::Corrupt("injected".into()));
+        }
+        self.inner.update(storage, tip, changes)
+    }
+
+    fn prove(&self, storage: &Storage, header: &BlockHeader, key: &AccountKey) -> Result<Option<(Option<Account>, StateProof)>, DbError> {
+        self.inner.prove(storage, header, key)
+    }
+}
+
+#[tokio::test]
+async fn test_a_failed_seal_keeps_its_transactions_pending() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[69; 32]);
+    let sender = Pubkey::new(key.verifying_key().to_bytes());
+    seed_account(&storage, AccountKey::native(sender), 1000);
+
+    let failures = Arc::new(AtomicUsize::new(1));
+    let (tx_sender, tx_receiver) = mpsc::channel(100);
+    let rollup_core = RollupCore::new(Arc::clone(&storage), tx_receiver, test_sequencer_config(), test_fees(), ChainId::DEVNET).await.unwrap()
+        .with_commitment(Box::new(FailingCommitment { inner: CommitmentScheme::default().build(), failures: Arc::clone(&failures) })).unwrap();
+    tokio::spawn(rollup_core.run());
+
+    let txs: Vec<Transaction> = (0..5).map(|nonce| signed_transfer(&key, Pubkey::new([70; 32]), 10, nonce)).collect();
+    for tx in &txs {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+
+    // The first seal fails; the seal timeout retries the same transactions.
+    let header = wait_for_batch(&storage, 1).await;
+    assert_eq!(failures.load(Ordering::SeqCst), 0);
+    assert_eq!(header.tx_root, merkle_root(&txs.iter().map(|tx| tx.hash().0).collect::<Vec<_>>()));
+    assert_eq!(storage.rocksdb.iterator_cf(storage.cf_pending_txs(), rocksdb::IteratorMode::Start).count(), 0);
+}
+
+#[tokio::test]
+async fn test_closing_the_channel_seals_the_final_batch() {
+    let storage = temp_storage().await;
+    let key = SigningKey::from_bytes(&[39; 32]);
+    seed_account(&storage, AccountKey::native(Pubkey::new(key.verifying_key().to_bytes())), 1000);
+
+    // Neither the size nor the latency limit would seal these.
+    let (tx_sender, tx_receiver) = mpsc::channel(100);
+    let config = SequencerConfig { seal_timeout: Duration::from_secs(60), ..SequencerConfig::default() };
+    let rollup_core = RollupCore::new(Arc::clone(&storage), tx_receiver, config, test_fees(), ChainId::DEVNET).await.unwrap();
+    let core_handle = tokio::spawn(rollup_core.run());
+    for nonce in 0..2 {
+        tx_sender.send(signed_transfer(&key, Pubkey::new([40; 32]), 10, nonce).into()).await.unwrap();
+    }
+
+    drop(tx_sender);
+    tokio::time::timeout(WAIT_LIMIT, core_handle).await.unwrap().unwrap();
+    let header = BlockHeader::from_bytes(&storage.rocksdb.get_cf(storage.cf_batches(), 1u64.to_be_bytes()).unwrap().unwrap()).unwrap();
+    assert_eq!(header.tx_count, 2);
+
+    storage.close().await.unwrap();
+    assert!(storage.sqlite.is_closed());
+}
+
+#[tokio::test]
+async fn test_parallel_execution_matches_sequential() {
+    let storage = temp_storage().await;
+    let collector = Pubkey::new([41; 32]);
+    let fees = FeeConfig { collector, min_fee: 1 };
+    let token = Pubkey::new([42; 32]);
+    let keys: Vec<SigningKey> = (43..49).map(|b| SigningKey::from_bytes(&[b; 32])).collect();
+    let owners: Vec<Pubkey> = keys.iter().map(|key| Pubkey::new(key.verifying_key().to_bytes())).collect();
+    for owner in &owners {
+        seed_account(&storage, AccountKey::native(*owner), 100);
+    }
+    seed_account(&storage, AccountKey::new(owners[0], token), 50);
+    seed_account(&storage, AccountKey::native(collector), 7);
+
+    let signed = |key: &SigningKey, recipient: Pubkey, tx_type: TransactionType, nonce: u64, fee: u64| {
+        let mut tx = Transaction {
+            chain_id: ChainId::DEVNET,
+            sender: Pubkey::new(key.verifying_key().to_bytes()),
+            recipient,
+            tx_type,
+            nonce,
+            fee,
+            valid_until_batch: None,
+            signature: Signature([0; 64]),
+        };
+        tx.sign(key);
+        tx
+    };
+    let deposit = Transaction {
+        chain_id: ChainId::DEVNET,
+        sender: SYSTEM_SENDER,
+        recipient: owners[5],
+        tx_type: TransactionType::Deposit { amount: 40, mint: NATIVE_MINT },
+        nonce: 0,
+        fee: 0,
+        valid_until_batch: None,
+        signature: Signature([0; 64]),
+    };
+    let txs = vec![
+        signed_transfer_with_fee(&keys[0], owners[1], 10, 0, 2),
+        signed_transfer_with_fee(&keys[2], owners[3], 20, 0, 1),
+        signed(&keys[0], owners[1], TransactionType::Transfer { amount: 5, mint: token }, 1, 1),
+        // Overdraws: fails, but pays its fee.
+        signed_transfer_with_fee(&keys[4], owners[5], 500, 0, 3),
+        deposit,
+        signed(&keys[2], owners[2], TransactionType::Withdraw { amount: 30, l1_recipient: Pubkey::new([49; 32]), mint: NATIVE_MINT }, 1, 1),
+        // Bad nonce: rejected.
+        signed_transfer_with_fee(&keys[1], owners[0], 1, 5, 1),
+        signed_transfer_with_fee(&keys[4], owners[5], 50, 1, 3),
+    ];
+    let groups = partition(&txs, &AccountKey::native(collector)).unwrap();
+    assert_eq!(groups, [vec![0, 2, 6], vec![1, 5], vec![3, 4, 7]]);
+
+    let assert_matches_sequential = |fees: FeeConfig, txs: &[Transaction]| {
+        let mut sequential = BatchContext::new(&storage, fees, ChainId::DEVNET, 1);
+        for tx in txs {
+            let _ = sequential.execute_transaction(tx);
+        }
+        let mut parallel = BatchContext::new(&storage, fees, ChainId::DEVNET, 1);
+        parallel.execute_batch(txs).unwrap();
+        parallel.check_supply().unwrap();
+        assert_eq!(parallel.write_set, sequential.write_set);
+        assert_eq!(parallel.receipts, sequential.receipts);
+        assert_eq!(parallel.withdrawals, sequential.withdrawals);
+        let root = |context: &BatchContext| AccountsFold.update(&storage, &BlockHeader::genesis(), &context.write_set).unwrap().root;
+        assert_eq!(root(&parallel), root(&sequential));
+        parallel
+    };
+    let parallel = assert_matches_sequential(fees, &txs);
+    assert_eq!(parallel.write_set[&AccountKey::native(collector)].balance, 7 + 2 + 1 + 1 + 3 + 1 + 3);
+    assert_eq!(parallel.withdrawals.len(), 1);
+
+    // Paying the collector directly reads its balance, so the batch runs in order.
+    let mut txs = txs;
+    txs.push(signed_transfer_with_fee(&keys[3], collector, 4, 0, 1));
+    assert!(partition(&txs, &AccountKey::native(collector)).is_none());
+    assert_matches_sequential(fees, &txs);
+
+    // A collector that sends spends its balance, including fees credited
+    // earlier in the batch, so that batch runs in order as well.
+    let collector_key = SigningKey::from_bytes(&[65; 32]);
+    let collector = Pubkey::new(collector_key.verifying_key().to_bytes());
+    seed_account(&storage, AccountKey::native(collector), 2);
+    let fees = FeeConfig { collector, min_fee: 1 };
+    txs.truncate(txs.len() - 1);
+    txs.push(signed_transfer_with_fee(&collector_key, owners[3], 6, 0, 1));
+    assert!(partition(&txs, &AccountKey::native(collector)).is_none());
+    let parallel = assert_matches_sequential(fees, &txs);
+    let receipt = parallel.receipts.last().unwrap();
+    assert_eq!((receipt.tx_hash, receipt.status), (txs[8].hash(), ReceiptStatus::Success));
+    assert_eq!(parallel.write_set[&AccountKey::native(collector)].balance, 2 + 2 + 1 + 1 + 3 + 1 + 3 - 6);
+}
+
+#[tokio::test]
+async fn test_block_header_v2_commits_to_transactions_and_receipts() {
+    let (addr, tx_sender, storage) = spawn_app().await;
+    let client = reqwest::Client::new();
+    let base_url = format!("http://{}", addr);
+
+    let key = SigningKey::from_bytes(&[1; 32]);
+    seed_account(&storage, AccountKey::native(Pubkey::new(key.verifying_key().to_bytes())), 1000);
+    // The last transfer overdraws, so the batch mixes successful and failed receipts.
+    let txs: Vec<_> = (0..5).map(|nonce| signed_transfer(&key, Pubkey::new([2; 32]), if nonce == 4 { 5000 } else { 10 }, nonce)).collect();
+    for tx in &txs {
+        tx_sender.send(tx.clone().into()).await.unwrap();
+    }
+    wait_for_receipt(&client, &base_url, txs[0].hash()).await;
+
+    let header: Value = client.get(format!("{}/v1/batches/1", base_url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(header["hdr_version"], 2);
+    let tx_hashes: Vec<[u8; 32]> = txs.iter().map(|tx| tx.hash().0).collect();
+    assert_eq!(header["tx_root"], hex::encode(merkle_root(&tx_hashes)));
+    let receipt_hashes: Vec<[u8; 32]> = tx_hashes.iter()
+        .map(|hash| bincode::deserialize::<Receipt>(&storage.rocksdb.get_cf(storage.cf_receipts(), hash).unwrap().unwrap()).unwrap().hash())
+        .collect();
+    assert_eq!(header["receipts_root"], hex::encode(merkle_root(&receipt_hashes)));
+
+    // Version 1 headers are still served, with the new roots zeroed.
+    let legacy = BlockHeader { hdr_version: 1, batch_id: 7, new_root: [3; 32], tx_count: 2, ..BlockHeader::genesis() };
+    let legacy_bytes = legacy.to_bytes().unwrap();
+    assert_eq!(legacy_bytes.len(), 96);
+    assert_eq!(BlockHeader::from_bytes(&legacy_bytes).unwrap(), legacy);
+    storage.rocksdb.put_cf(storage.cf_batches(), 7u64.to_be_bytes(), &legacy_bytes).unwrap();
+    let served: Value = client.get(format!("{}/v1/batches/7", base_url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(served["hdr_version"], 1);
+    assert_eq!(served["new_root"], hex::encode([3; 32]));
+    assert_eq!(served["tx_root"], hex::encode([0; 32]));
+
+    let mut truncated = BlockHeader::genesis().to_bytes().unwrap();
+    truncated.truncate(96);
+    assert!(BlockHeader::from_bytes(&truncated).is_err());
+}
+
+#[test]
+fn test_merkle_root_layout() {
+    let leaf = |leaf: &[u8; 32]| -> [u8; 32] { Sha256::new().chain_update([0]).chain_update(leaf).finalize().into() };
+    let node = |left: [u8; 32], right: [u8; 32]| -> [u8; 32] { Sha256::new().chain_update([1]).chain_update(left).chain_update(right).finalize().into() };
+    let leaves = [[1; 32], [2; 32], [3; 32]];
+
+    assert_eq!(merkle_root(&[]), [0; 32]);
+    assert_eq!(merkle_root(&leaves[..1]), leaf(&leaves[0]));
+    // The odd third leaf moves up a level unpaired.
+    assert_eq!(merkle_root(&leaves), node(node(leaf(&leaves[0]), leaf(&leaves[1])), leaf(&leaves[2])));
+}